@@ -1,6 +1,9 @@
 use std::{error::Error, hash::Hash};
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
 pub trait Storable {
@@ -10,6 +13,10 @@ pub trait Storable {
     fn id(&self) -> Uuid;
     fn sku(&self) -> &Self::Sku;
     fn metadata(&self) -> &Self::Metadata;
+    /// When the item was first added.
+    fn created_at(&self) -> DateTime<Utc>;
+    /// When the SKU or metadata last changed, the creation time if never.
+    fn updated_at(&self) -> DateTime<Utc>;
 }
 
 #[derive(Clone, Debug)]
@@ -21,6 +28,8 @@ where
     id: Uuid,
     sku: S,
     metadata: M,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
 }
 
 impl<S, M> Item<S, M>
@@ -30,7 +39,8 @@ where
 {
     pub fn new(sku: S, metadata: M) -> Self {
         let id = Uuid::new_v4();
-        Self { id, sku, metadata }
+        let now = Utc::now();
+        Self { id, sku, metadata, created_at: now, updated_at: now }
     }
 }
 
@@ -53,6 +63,14 @@ where
     fn metadata(&self) -> &Self::Metadata {
         &self.metadata
     }
+
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
 }
 
 #[async_trait]
@@ -62,10 +80,84 @@ pub trait Repository<T: Storable> {
     async fn get_by_id(&self, id: Uuid) -> Result<Option<T>, Self::RepositoryError>;
     async fn get_by_sku(&self, sku: &T::Sku) -> Result<Option<T>, Self::RepositoryError>;
     async fn add(&self, item: T) -> Result<(), Self::RepositoryError>;
+    /// Replace the SKU and metadata of the item with the same id.
+    async fn update(&self, item: T) -> Result<(), Self::RepositoryError>;
     async fn delete(&self, id: Uuid) -> Result<(), Self::RepositoryError>;
     async fn list(&self) -> Result<Vec<T>, Self::RepositoryError>;
 }
 
+/// A repository that moves deleted items to a trash, from which they can be restored
+/// until they are purged.
+#[async_trait]
+pub trait Trash<T: Storable>: Repository<T> {
+    /// Every item, including the ones in the trash.
+    async fn list_with_deleted(&self) -> Result<Vec<T>, Self::RepositoryError>;
+    /// Take an item out of the trash.
+    async fn restore(&self, id: Uuid) -> Result<(), Self::RepositoryError>;
+    /// Remove an item in the trash for good.
+    async fn purge(&self, id: Uuid) -> Result<(), Self::RepositoryError>;
+    /// Remove the items moved to the trash before a time, returning how many there were.
+    async fn purge_deleted_before(&self, before: DateTime<Utc>) -> Result<usize, Self::RepositoryError>;
+}
+
+/// A timestamp of `Storable` items can be queried by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemTimestamp {
+    Created,
+    Updated,
+}
+
+/// A repository that can list items by time, e.g. the ones added at an event.
+#[async_trait]
+pub trait TimeRange<T: Storable>: Repository<T> {
+    /// The items not in the trash with the timestamp in `[from, to)`, oldest first.
+    async fn list_between(
+        &self,
+        timestamp: ItemTimestamp,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<T>, Self::RepositoryError>;
+}
+
+/// A change to the items of a repository.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", content = "item", rename_all = "snake_case")]
+pub enum RepositoryEvent<T> {
+    Added(T),
+    Updated(T),
+    /// Moved to the trash, or removed if the repository has no trash.
+    Deleted(T),
+    /// Restored from the trash.
+    Restored(T),
+    /// Removed from the trash for good.
+    Purged(T),
+    /// The storage was changed by someone else, e.g. another process sharing the
+    /// database. The changed items are unknown, so listeners should reload.
+    External,
+}
+
+impl<T> RepositoryEvent<T> {
+    /// Convert the item of the event, e.g. to the type sent to a client.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> RepositoryEvent<U> {
+        match self {
+            RepositoryEvent::Added(x) => RepositoryEvent::Added(f(x)),
+            RepositoryEvent::Updated(x) => RepositoryEvent::Updated(f(x)),
+            RepositoryEvent::Deleted(x) => RepositoryEvent::Deleted(f(x)),
+            RepositoryEvent::Restored(x) => RepositoryEvent::Restored(f(x)),
+            RepositoryEvent::Purged(x) => RepositoryEvent::Purged(f(x)),
+            RepositoryEvent::External => RepositoryEvent::External,
+        }
+    }
+}
+
+/// A repository that publishes its changes.
+pub trait Observable<T: Storable> {
+    /// Receive the changes made from now on. A receiver that falls behind gets
+    /// `RecvError::Lagged` and should reload, as after `RepositoryEvent::External`.
+    fn subscribe(&self) -> broadcast::Receiver<RepositoryEvent<T>>;
+}
+
 #[derive(Debug, Clone)]
 pub struct ItemRegisterEvent<S, M>
 where
@@ -78,7 +170,8 @@ where
     pub source: ItemRegisterSource,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ItemRegisterSource {
     Manual,
     QrCode,
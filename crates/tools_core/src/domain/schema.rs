@@ -0,0 +1,191 @@
+use std::fmt;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// A field of the metadata that failed validation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    /// The metadata key, empty if the metadata as a whole is invalid.
+    pub key: String,
+    pub message: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.key.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.key, self.message)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SchemaError {
+    #[error("invalid metadata: {}", .0.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", "))]
+    Invalid(Vec<FieldError>),
+}
+
+/// The type of a metadata field and its constraints.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FieldKind {
+    Text {
+        #[serde(default)]
+        max_length: Option<usize>,
+    },
+    Integer {
+        #[serde(default)]
+        min: Option<i64>,
+        #[serde(default)]
+        max: Option<i64>,
+    },
+    Number {
+        #[serde(default)]
+        min: Option<f64>,
+        #[serde(default)]
+        max: Option<f64>,
+        /// Shown next to the value, e.g. `cm`.
+        #[serde(default)]
+        unit: Option<String>,
+    },
+    Boolean,
+    /// One of a fixed list of strings.
+    Choice { options: Vec<String> },
+    /// A `YYYY-MM-DD` date.
+    Date,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldSchema {
+    pub key: String,
+    /// The name shown in forms, the key if not set.
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(flatten)]
+    pub kind: FieldKind,
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// The fields expected in the metadata of a collection, stored as a JSON object.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetadataSchema {
+    pub fields: Vec<FieldSchema>,
+    /// Whether keys not declared in `fields` are allowed.
+    #[serde(default = "default_true")]
+    pub allow_other: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl FieldKind {
+    fn check(&self, value: &Value) -> Result<(), String> {
+        match self {
+            FieldKind::Text { max_length } => {
+                let s = value.as_str().ok_or("expected a string")?;
+                match max_length {
+                    Some(max) if s.chars().count() > *max => Err(format!("longer than {} characters", max)),
+                    _ => Ok(()),
+                }
+            }
+            FieldKind::Integer { min, max } => {
+                let x = value.as_i64().ok_or("expected an integer")?;
+                check_range(x, *min, *max)
+            }
+            FieldKind::Number { min, max, .. } => {
+                let x = value.as_f64().ok_or("expected a number")?;
+                check_range(x, *min, *max)
+            }
+            FieldKind::Boolean => value.as_bool().map(|_| ()).ok_or_else(|| "expected true or false".to_string()),
+            FieldKind::Choice { options } => {
+                let s = value.as_str().ok_or("expected a string")?;
+                if options.iter().any(|x| x == s) {
+                    Ok(())
+                } else {
+                    Err(format!("expected one of {}", options.join(", ")))
+                }
+            }
+            FieldKind::Date => {
+                let s = value.as_str().ok_or("expected a date")?;
+                NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                    .map(|_| ())
+                    .map_err(|_| format!("`{}` is not a YYYY-MM-DD date", s))
+            }
+        }
+    }
+}
+
+fn check_range<T: PartialOrd + fmt::Display>(x: T, min: Option<T>, max: Option<T>) -> Result<(), String> {
+    if let Some(min) = min.filter(|min| x < *min) {
+        return Err(format!("less than {}", min));
+    }
+    if let Some(max) = max.filter(|max| x > *max) {
+        return Err(format!("greater than {}", max));
+    }
+    Ok(())
+}
+
+impl MetadataSchema {
+    /// Check metadata against the schema, collecting every invalid field.
+    ///
+    /// Missing optional fields and `null` values are accepted.
+    ///
+    /// # Errors
+    /// * `SchemaError::Invalid` with one `FieldError` per invalid field, or a single error
+    ///   with an empty key if the metadata is not a JSON object.
+    pub fn validate(&self, metadata: &str) -> Result<(), SchemaError> {
+        let object: Map<String, Value> = serde_json::from_str(metadata).map_err(|e| {
+            SchemaError::Invalid(vec![FieldError { key: String::new(), message: format!("expected a JSON object: {}", e) }])
+        })?;
+        let mut errors = Vec::new();
+        for field in &self.fields {
+            let rs = match object.get(&field.key) {
+                None | Some(Value::Null) if field.required => Err("required".to_string()),
+                None | Some(Value::Null) => Ok(()),
+                Some(value) => field.kind.check(value),
+            };
+            if let Err(message) = rs {
+                errors.push(FieldError { key: field.key.clone(), message });
+            }
+        }
+        if !self.allow_other {
+            for key in object.keys().filter(|key| !self.fields.iter().any(|x| &x.key == *key)) {
+                errors.push(FieldError { key: key.clone(), message: "unknown field".to_string() });
+            }
+        }
+        if errors.is_empty() { Ok(()) } else { Err(SchemaError::Invalid(errors)) }
+    }
+}
+
+#[test]
+fn test_validate_metadata() {
+    let schema: MetadataSchema = serde_json::from_str(r#"{
+        "fields": [
+            { "key": "character", "type": "text", "required": true },
+            { "key": "size", "type": "number", "min": 0, "unit": "cm" },
+            { "key": "edition", "type": "choice", "options": ["standard", "limited"] },
+            { "key": "release", "type": "date" },
+            { "key": "count", "type": "integer", "max": 10 }
+        ],
+        "allow_other": false
+    }"#).unwrap();
+
+    assert!(schema.validate(r#"{"character": "香澄", "size": 20, "release": "2025-08-09"}"#).is_ok());
+    assert!(schema.validate(r#"{"character": "香澄", "edition": null}"#).is_ok());
+
+    let SchemaError::Invalid(errors) = schema.validate(
+        r#"{"size": -1, "edition": "deluxe", "release": "2025/08/09", "count": 1.5, "color": "red"}"#
+    ).unwrap_err();
+    let keys: Vec<_> = errors.iter().map(|x| x.key.as_str()).collect();
+    assert_eq!(keys, ["character", "size", "edition", "release", "count", "color"]);
+    assert_eq!(errors[1].message, "less than 0");
+
+    let SchemaError::Invalid(errors) = schema.validate("角色: 香澄").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].key, "");
+}
@@ -0,0 +1,174 @@
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SkuError {
+    #[error("SKU is empty")]
+    Empty,
+    #[error("`{0}` has an invalid check digit, expected {1}")]
+    InvalidCheckDigit(String, char),
+    #[error("`{0}` is not a UPC-E code")]
+    InvalidUpcE(String),
+    #[error("`{0}` is not an EAN, UPC, ISBN or GTIN code")]
+    NotAProductCode(String),
+}
+
+/// The GS1 check digit of the digits before it, as used by EAN, UPC, JAN and ISBN-13.
+///
+/// # Arguments
+/// * `digits` - The digits without the check digit, as values 0 to 9.
+pub fn gtin_check_digit(digits: &[u8]) -> u8 {
+    let sum: u32 = digits.iter().rev()
+        .enumerate()
+        .map(|(i, &x)| if i % 2 == 0 { 3 * x as u32 } else { x as u32 })
+        .sum();
+    ((10 - sum % 10) % 10) as u8
+}
+
+fn to_digits(code: &str) -> Vec<u8> {
+    code.bytes().map(|x| x - b'0').collect()
+}
+
+fn check_gtin(code: &str) -> Result<(), SkuError> {
+    let digits = to_digits(code);
+    let (body, check) = digits.split_at(digits.len() - 1);
+    let expected = gtin_check_digit(body);
+    if check[0] != expected {
+        return Err(SkuError::InvalidCheckDigit(code.to_string(), (b'0' + expected) as char));
+    }
+    Ok(())
+}
+
+fn isbn10_to_ean13(code: &str) -> Result<String, SkuError> {
+    let sum: u32 = code.bytes()
+        .enumerate()
+        .map(|(i, x)| (10 - i as u32) * if x == b'X' { 10 } else { (x - b'0') as u32 })
+        .sum();
+    if !sum.is_multiple_of(11) {
+        let body: u32 = code.bytes().take(9).enumerate().map(|(i, x)| (10 - i as u32) * (x - b'0') as u32).sum();
+        let expected = match (11 - body % 11) % 11 {
+            10 => 'X',
+            x => (b'0' + x as u8) as char,
+        };
+        return Err(SkuError::InvalidCheckDigit(code.to_string(), expected));
+    }
+    let body = format!("978{}", &code[..9]);
+    let check = gtin_check_digit(&to_digits(&body));
+    Ok(format!("{}{}", body, check))
+}
+
+/// Expand a zero-suppressed UPC-E code to the UPC-A code it stands for.
+///
+/// # Arguments
+/// * `code` - 8 digits: the number system 0 or 1, 6 digits and the check digit.
+///
+/// # Errors
+/// * `SkuError::InvalidUpcE` if the code is not 8 digits starting with 0 or 1.
+/// * `SkuError::InvalidCheckDigit` if the check digit does not match the expanded code.
+pub fn expand_upc_e(code: &str) -> Result<String, SkuError> {
+    let b = code.as_bytes();
+    if b.len() != 8 || !b.iter().all(u8::is_ascii_digit) || !matches!(b[0], b'0' | b'1') {
+        return Err(SkuError::InvalidUpcE(code.to_string()));
+    }
+    let d = |i: usize| b[i] as char;
+    let body: String = match b[6] {
+        b'0'..=b'2' => [d(1), d(2), d(6), '0', '0', '0', '0', d(3), d(4), d(5)].iter().collect(),
+        b'3' => [d(1), d(2), d(3), '0', '0', '0', '0', '0', d(4), d(5)].iter().collect(),
+        b'4' => [d(1), d(2), d(3), d(4), '0', '0', '0', '0', '0', d(5)].iter().collect(),
+        _ => [d(1), d(2), d(3), d(4), d(5), '0', '0', '0', '0', d(6)].iter().collect(),
+    };
+    let upc_a = format!("{}{}{}", d(0), body, d(7));
+    check_gtin(&upc_a)?;
+    Ok(upc_a)
+}
+
+/// Normalize a SKU so the same product is always stored under the same string.
+///
+/// Whitespace is removed. SKUs of 8, 12, 13 or 14 digits, and ISBNs of 10 or 13 digits
+/// with optional hyphens and a trailing `X` for ISBN-10, are treated as product codes:
+/// the check digit is validated and the code is canonicalized to its GTIN form.
+/// * UPC-A (12 digits) becomes EAN-13 with a leading zero.
+/// * ISBN-10 becomes its `978` EAN-13, ISBN-13 is already an EAN-13.
+/// * GTIN-14 with a leading zero becomes EAN-13.
+/// * EAN-8 and EAN-13/JAN are kept as they are.
+///
+/// Any other SKU, e.g. `FUMO-0042`, `0042` or `2025-08-09`, is returned without whitespace.
+///
+/// # Errors
+/// * `SkuError::Empty` if nothing is left after removing whitespace.
+/// * `SkuError::InvalidCheckDigit` if the check digit of a product code is wrong.
+pub fn normalize_sku(sku: &str) -> Result<String, SkuError> {
+    let sku: String = sku.chars().filter(|x| !x.is_whitespace()).collect();
+    if sku.is_empty() {
+        return Err(SkuError::Empty);
+    }
+    let code: String = sku.chars().filter(|x| *x != '-').map(|x| x.to_ascii_uppercase()).collect();
+    // Only ISBNs are written with hyphens, a hyphenated SKU of another length is a date or an internal code.
+    let hyphenated = code.len() != sku.len();
+    let (body, last) = code.split_at(code.len().saturating_sub(1));
+    let digits = body.bytes().all(|x| x.is_ascii_digit());
+    match code.len() {
+        10 if digits && (last == "X" || last.bytes().all(|x| x.is_ascii_digit())) => isbn10_to_ean13(&code),
+        _ if !digits || !last.bytes().all(|x| x.is_ascii_digit()) => Ok(sku),
+        13 => check_gtin(&code).map(|_| code),
+        8 if !hyphenated => check_gtin(&code).map(|_| code),
+        12 if !hyphenated => check_gtin(&code).map(|_| format!("0{}", code)),
+        14 if !hyphenated => {
+            check_gtin(&code)?;
+            Ok(code.strip_prefix('0').map_or(code.clone(), str::to_string))
+        }
+        _ => Ok(sku),
+    }
+}
+
+/// Normalize a product code like `normalize_sku`, refusing anything else.
+///
+/// # Errors
+/// * `SkuError::NotAProductCode` if the code is not an EAN, UPC, ISBN or GTIN, e.g. an
+///   internal SKU like `FUMO-0042`.
+/// * The errors of `normalize_sku`.
+pub fn normalize_product_code(code: &str) -> Result<String, SkuError> {
+    let normalized = normalize_sku(code)?;
+    // Product codes come out of `normalize_sku` as 8, 13 or 14 digits, anything else was kept as it was.
+    if !matches!(normalized.len(), 8 | 13 | 14) || !normalized.bytes().all(|x| x.is_ascii_digit()) {
+        return Err(SkuError::NotAProductCode(normalized));
+    }
+    Ok(normalized)
+}
+
+#[test]
+fn test_normalize_sku() {
+    assert_eq!(normalize_sku(" 4 901234 567894\n").unwrap(), "4901234567894");
+    assert_eq!(normalize_sku("036000291452").unwrap(), "0036000291452");
+    assert_eq!(normalize_sku("14901234567891").unwrap(), "14901234567891");
+    assert_eq!(normalize_sku("04901234567894").unwrap(), "4901234567894");
+    assert_eq!(normalize_sku("45496608").unwrap(), "45496608");
+    assert_eq!(normalize_sku("4-04-102622-9").unwrap(), "9784041026229");
+    assert_eq!(normalize_sku("978-4-04-102622-9").unwrap(), "9784041026229");
+    assert_eq!(normalize_sku("FUMO 0042").unwrap(), "FUMO0042");
+    assert_eq!(normalize_sku("ふもふも 1").unwrap(), "ふもふも1");
+
+    assert_eq!(normalize_sku(" \t").unwrap_err(), SkuError::Empty);
+    assert_eq!(normalize_sku("4901234567890").unwrap_err(), SkuError::InvalidCheckDigit("4901234567890".to_string(), '4'));
+    assert_eq!(normalize_sku("4-04-102622-X").unwrap_err(), SkuError::InvalidCheckDigit("404102622X".to_string(), '9'));
+    // Internal SKUs that are not the length of a product code are kept as they are.
+    for sku in ["0042", "12345", "2025-08-09", "12X", "1-2", "-", "123456789012345"] {
+        assert_eq!(normalize_sku(sku).unwrap(), sku);
+    }
+}
+
+#[test]
+fn test_normalize_product_code() {
+    assert_eq!(normalize_product_code("036000291452").unwrap(), "0036000291452");
+    assert_eq!(normalize_product_code("4-04-102622-9").unwrap(), "9784041026229");
+    assert_eq!(normalize_product_code("45496608").unwrap(), "45496608");
+    assert!(matches!(normalize_product_code("4901234567890"), Err(SkuError::InvalidCheckDigit(..))));
+    for code in ["FUMO-0042", "hello", "0042", "1234-5670", "123456789012345"] {
+        assert!(matches!(normalize_product_code(code), Err(SkuError::NotAProductCode(_))), "{}", code);
+    }
+}
+
+#[test]
+fn test_expand_upc_e() {
+    assert_eq!(expand_upc_e("01234565").unwrap(), "012345000065");
+    assert_eq!(normalize_sku(&expand_upc_e("01234565").unwrap()).unwrap(), "0012345000065");
+    assert!(matches!(expand_upc_e("01234566"), Err(SkuError::InvalidCheckDigit(..))));
+    assert!(matches!(expand_upc_e("21234565"), Err(SkuError::InvalidUpcE(_))));
+}
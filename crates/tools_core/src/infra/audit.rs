@@ -0,0 +1,495 @@
+use std::{fs, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::warn;
+use rusqlite::Connection;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::domain::repository::{ItemRegisterSource, ItemTimestamp, Repository, Storable, TimeRange, Trash};
+use crate::infra::repository::encryption::{self, EncryptionError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditLogError {
+    #[error(transparent)]
+    RusqliteError(#[from] rusqlite::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error("Task failed to execute: {0}")]
+    JoinError(#[from] tokio::task::JoinError),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    EncryptionError(#[from] EncryptionError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditError<E> {
+    #[error(transparent)]
+    Repository(E),
+    #[error("audit log: {0}")]
+    Log(#[from] AuditLogError),
+    #[error("audit entry {0} not found for this item")]
+    EntryNotFound(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    Add,
+    Update,
+    Delete,
+    Restore,
+    Purge,
+}
+
+impl AuditOperation {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AuditOperation::Add => "add",
+            AuditOperation::Update => "update",
+            AuditOperation::Delete => "delete",
+            AuditOperation::Restore => "restore",
+            AuditOperation::Purge => "purge",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        [Self::Add, Self::Update, Self::Delete, Self::Restore, Self::Purge]
+            .into_iter()
+            .find(|x| x.as_str() == s)
+    }
+}
+
+/// Who makes the changes recorded by an `Audited` repository.
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    pub operator: String,
+    pub source: ItemRegisterSource,
+}
+
+/// A recorded change of an item, with its state before and after.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry<T> {
+    pub id: i64,
+    pub item_id: Uuid,
+    pub operation: AuditOperation,
+    pub operator: String,
+    pub source: ItemRegisterSource,
+    pub at: DateTime<Utc>,
+    /// `None` if the item did not exist, e.g. for an add.
+    pub before: Option<T>,
+    /// `None` if the item no longer exists, e.g. for a delete.
+    pub after: Option<T>,
+}
+
+impl<T> AuditEntry<T> {
+    /// Convert the snapshots of the entry, e.g. to the type sent to a client.
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> AuditEntry<U> {
+        AuditEntry {
+            id: self.id,
+            item_id: self.item_id,
+            operation: self.operation,
+            operator: self.operator,
+            source: self.source,
+            at: self.at,
+            before: self.before.map(&mut f),
+            after: self.after.map(&mut f),
+        }
+    }
+}
+
+/// Writes audit entries on the connection of the change they record, so that the change
+/// and its entry are committed or rolled back together.
+#[derive(Debug, Clone)]
+pub struct AuditRecorder {
+    collection: String,
+    context: AuditContext,
+}
+
+impl AuditRecorder {
+    /// Insert an entry into the `audit_log` table, which must be in the database of `conn`.
+    pub fn record<T: Serialize>(
+        &self,
+        conn: &Connection,
+        item_id: Uuid,
+        operation: AuditOperation,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) -> rusqlite::Result<()> {
+        let to_json = |x: &T| serde_json::to_string(x).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)));
+        conn.execute(
+            "INSERT INTO audit_log (collection, item_id, operation, operator, source, at, before, after)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            (
+                &self.collection,
+                item_id.to_string(),
+                operation.as_str(),
+                &self.context.operator,
+                serde_json::to_string(&self.context.source).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?,
+                Utc::now().timestamp_millis(),
+                before.map(to_json).transpose()?,
+                after.map(to_json).transpose()?,
+            ),
+        )?;
+        Ok(())
+    }
+}
+
+/// A repository that can record its changes in the same transaction as it makes them.
+#[async_trait]
+pub trait RecordedRepository<T: Storable>: Trash<T> {
+    async fn add_recorded(&self, item: T, recorder: &AuditRecorder) -> Result<(), Self::RepositoryError>;
+    async fn update_recorded(&self, item: T, recorder: &AuditRecorder) -> Result<(), Self::RepositoryError>;
+    async fn delete_recorded(&self, id: Uuid, recorder: &AuditRecorder) -> Result<(), Self::RepositoryError>;
+    async fn restore_recorded(&self, id: Uuid, recorder: &AuditRecorder) -> Result<(), Self::RepositoryError>;
+    async fn purge_recorded(&self, id: Uuid, recorder: &AuditRecorder) -> Result<(), Self::RepositoryError>;
+    async fn purge_deleted_before_recorded(&self, before: DateTime<Utc>, recorder: &AuditRecorder) -> Result<usize, Self::RepositoryError>;
+}
+
+/// The changes of a collection, stored in the `audit_log` table of its database.
+pub struct SqliteAuditLog {
+    db_path: String,
+    collection: String,
+    passphrase: Option<String>,
+}
+
+impl SqliteAuditLog {
+    pub fn new(db_path: &str, collection: String) -> Self {
+        Self { db_path: db_path.to_string(), collection, passphrase: None }
+    }
+
+    /// Unlock an encrypted database with a SQLCipher passphrase.
+    pub fn with_passphrase(mut self, passphrase: String) -> Self {
+        self.passphrase = Some(passphrase);
+        self
+    }
+
+    pub async fn create_table(&self) -> Result<(), AuditLogError> {
+        let db_path = PathBuf::from(&self.db_path);
+        let passphrase = self.passphrase.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Some(parent_dir) = db_path.parent() {
+                fs::create_dir_all(parent_dir)?
+            }
+            let conn = encryption::open(db_path, passphrase.as_deref())?;
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS audit_log (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    collection TEXT NOT NULL,
+                    item_id TEXT NOT NULL,
+                    operation TEXT NOT NULL,
+                    operator TEXT NOT NULL,
+                    source TEXT NOT NULL,
+                    at INTEGER NOT NULL,
+                    before TEXT,
+                    after TEXT
+                );
+                CREATE INDEX IF NOT EXISTS audit_log_item ON audit_log (collection, item_id);",
+            )?;
+            Ok(())
+        })
+        .await?
+    }
+
+    /// A recorder of changes made as `context` to the collection of this log.
+    pub fn recorder(&self, context: AuditContext) -> AuditRecorder {
+        AuditRecorder { collection: self.collection.clone(), context }
+    }
+
+    /// The changes of an item, oldest first.
+    pub async fn history<T: DeserializeOwned + Send + 'static>(&self, item_id: Uuid) -> Result<Vec<AuditEntry<T>>, AuditLogError> {
+        self.query("item_id = ?2", (self.collection.clone(), item_id.to_string())).await
+    }
+
+    async fn entry<T: DeserializeOwned + Send + 'static>(&self, item_id: Uuid, entry_id: i64) -> Result<Option<AuditEntry<T>>, AuditLogError> {
+        let rs = self.query("item_id = ?2 AND id = ?3", (self.collection.clone(), item_id.to_string(), entry_id)).await?;
+        Ok(rs.into_iter().next())
+    }
+
+    async fn query<T, P>(&self, condition: &str, params: P) -> Result<Vec<AuditEntry<T>>, AuditLogError>
+    where
+        T: DeserializeOwned + Send + 'static,
+        P: rusqlite::Params + Send + 'static,
+    {
+        let db_path = self.db_path.clone();
+        let passphrase = self.passphrase.clone();
+        let sql = format!(
+            "SELECT id, item_id, operation, operator, source, at, before, after FROM audit_log
+             WHERE collection = ?1 AND {} ORDER BY id",
+            condition
+        );
+        tokio::task::spawn_blocking(move || {
+            let conn = encryption::open(db_path, passphrase.as_deref())?;
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(params, |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, i64>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                    row.get::<_, Option<String>>(7)?,
+                ))
+            })?;
+            let mut rs = Vec::new();
+            for x in rows {
+                let (id, item_id, operation, operator, source, at, before, after) = x?;
+                let Some(operation) = AuditOperation::parse(&operation) else {
+                    warn!("Skipping audit entry {} with unknown operation `{}`", id, operation);
+                    continue;
+                };
+                rs.push(AuditEntry {
+                    id,
+                    item_id: Uuid::parse_str(&item_id).map_err(|e| rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, Box::new(e)))?,
+                    operation,
+                    operator,
+                    source: serde_json::from_str(&source)?,
+                    at: DateTime::from_timestamp_millis(at).unwrap_or_default(),
+                    before: before.map(|x| serde_json::from_str(&x)).transpose()?,
+                    after: after.map(|x| serde_json::from_str(&x)).transpose()?,
+                });
+            }
+            Ok(rs)
+        })
+        .await?
+    }
+}
+
+/// A repository that records every change made through it in an audit log.
+///
+/// The context is fixed for the wrapper, so build one per operator and source, e.g. per
+/// request. A change and its entry are committed in one transaction, so if recording
+/// fails the change is rolled back and the error of the repository is returned.
+pub struct Audited<R> {
+    inner: Arc<R>,
+    log: Arc<SqliteAuditLog>,
+    recorder: AuditRecorder,
+}
+
+impl<R> Audited<R> {
+    pub fn new(inner: Arc<R>, log: Arc<SqliteAuditLog>, context: AuditContext) -> Self {
+        let recorder = log.recorder(context);
+        Self { inner, log, recorder }
+    }
+
+    pub fn log(&self) -> &SqliteAuditLog {
+        &self.log
+    }
+
+    async fn find_with_deleted<T>(&self, id: Uuid) -> Result<Option<T>, AuditError<R::RepositoryError>>
+    where
+        T: Storable + Send + 'static,
+        R: Trash<T> + Sync,
+    {
+        let items = self.inner.list_with_deleted().await.map_err(AuditError::Repository)?;
+        Ok(items.into_iter().find(|x| x.id() == id))
+    }
+
+    /// Put an item back in the state it had after a recorded change, restoring or
+    /// re-adding it if needed. The revert is recorded as the changes it makes.
+    ///
+    /// # Errors
+    /// * `AuditError::EntryNotFound` if the entry is not one of the item's.
+    pub async fn revert<T>(&self, id: Uuid, entry_id: i64) -> Result<(), AuditError<R::RepositoryError>>
+    where
+        T: Storable + Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+        R: RecordedRepository<T> + Send + Sync,
+    {
+        let entry: AuditEntry<T> = self.log.entry(id, entry_id).await?
+            .ok_or(AuditError::EntryNotFound(entry_id))?;
+        let active = self.inner.get_by_id(id).await.map_err(AuditError::Repository)?.is_some();
+        match entry.after {
+            None if active => self.delete(id).await,
+            None => Ok(()),
+            Some(target) if active => self.update(target).await,
+            Some(target) => {
+                if self.find_with_deleted::<T>(id).await?.is_some() {
+                    Trash::<T>::restore(self, id).await?;
+                    self.update(target).await
+                } else {
+                    self.add(target).await
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<T, R> Repository<T> for Audited<R>
+where
+    T: Storable + Serialize + Clone + Send + Sync + 'static,
+    R: RecordedRepository<T> + Send + Sync,
+{
+    type RepositoryError = AuditError<R::RepositoryError>;
+
+    async fn get_by_id(&self, id: Uuid) -> Result<Option<T>, Self::RepositoryError> {
+        self.inner.get_by_id(id).await.map_err(AuditError::Repository)
+    }
+
+    async fn get_by_sku(&self, sku: &T::Sku) -> Result<Option<T>, Self::RepositoryError> {
+        self.inner.get_by_sku(sku).await.map_err(AuditError::Repository)
+    }
+
+    async fn add(&self, item: T) -> Result<(), Self::RepositoryError> {
+        self.inner.add_recorded(item, &self.recorder).await.map_err(AuditError::Repository)
+    }
+
+    async fn update(&self, item: T) -> Result<(), Self::RepositoryError> {
+        self.inner.update_recorded(item, &self.recorder).await.map_err(AuditError::Repository)
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<(), Self::RepositoryError> {
+        self.inner.delete_recorded(id, &self.recorder).await.map_err(AuditError::Repository)
+    }
+
+    async fn list(&self) -> Result<Vec<T>, Self::RepositoryError> {
+        self.inner.list().await.map_err(AuditError::Repository)
+    }
+}
+
+#[async_trait]
+impl<T, R> Trash<T> for Audited<R>
+where
+    T: Storable + Serialize + Clone + Send + Sync + 'static,
+    R: RecordedRepository<T> + Send + Sync,
+{
+    async fn list_with_deleted(&self) -> Result<Vec<T>, Self::RepositoryError> {
+        self.inner.list_with_deleted().await.map_err(AuditError::Repository)
+    }
+
+    async fn restore(&self, id: Uuid) -> Result<(), Self::RepositoryError> {
+        self.inner.restore_recorded(id, &self.recorder).await.map_err(AuditError::Repository)
+    }
+
+    async fn purge(&self, id: Uuid) -> Result<(), Self::RepositoryError> {
+        self.inner.purge_recorded(id, &self.recorder).await.map_err(AuditError::Repository)
+    }
+
+    async fn purge_deleted_before(&self, before: DateTime<Utc>) -> Result<usize, Self::RepositoryError> {
+        self.inner.purge_deleted_before_recorded(before, &self.recorder).await.map_err(AuditError::Repository)
+    }
+}
+
+#[async_trait]
+impl<T, R> TimeRange<T> for Audited<R>
+where
+    T: Storable + Serialize + Clone + Send + Sync + 'static,
+    R: TimeRange<T> + RecordedRepository<T> + Send + Sync,
+{
+    async fn list_between(
+        &self,
+        timestamp: ItemTimestamp,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<T>, Self::RepositoryError> {
+        self.inner.list_between(timestamp, from, to).await.map_err(AuditError::Repository)
+    }
+}
+
+#[cfg(test)]
+fn test_audited(name: &str) -> (std::path::PathBuf, Audited<crate::infra::repository::sqlite::SqliteRepository>) {
+    use crate::infra::repository::sqlite::{test_db, SqliteRepository};
+
+    let path = test_db(name);
+    let db_path = path.to_str().unwrap();
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let repo = rt.block_on(async {
+        let repo = SqliteRepository::new(db_path, "items".to_string()).await;
+        repo.create_table().await.unwrap();
+        let log = SqliteAuditLog::new(db_path, "items".to_string());
+        log.create_table().await.unwrap();
+        let context = AuditContext { operator: "tester".to_string(), source: ItemRegisterSource::Manual };
+        Audited::new(Arc::new(repo), Arc::new(log), context)
+    });
+    (path, repo)
+}
+
+#[test]
+fn test_audit_history_and_revert() {
+    use crate::infra::repository::sqlite::SqliteRepositoryItem;
+
+    let (path, repo) = test_audited("audit");
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let item = SqliteRepositoryItem::new("FUMO-0001".to_string(), "{\"name\":\"a\"}".to_string());
+        let id = item.id();
+        repo.add(item.clone()).await.unwrap();
+        repo.update(SqliteRepositoryItem::with_id(id, "FUMO-0001".to_string(), "{\"name\":\"b\"}".to_string())).await.unwrap();
+        let history: Vec<AuditEntry<SqliteRepositoryItem>> = repo.log().history(id).await.unwrap();
+        let operations: Vec<_> = history.iter().map(|x| x.operation).collect();
+        assert_eq!(operations, [AuditOperation::Add, AuditOperation::Update]);
+        assert!(history[0].before.is_none());
+        assert_eq!(history[1].before.as_ref().unwrap().metadata(), "{\"name\":\"a\"}");
+        assert_eq!(history[1].after.as_ref().unwrap().metadata(), "{\"name\":\"b\"}");
+        assert_eq!(history[1].operator, "tester");
+        let added = history[0].id;
+
+        // An active item is updated back.
+        repo.revert::<SqliteRepositoryItem>(id, added).await.unwrap();
+        assert_eq!(repo.get_by_id(id).await.unwrap().unwrap().metadata(), "{\"name\":\"a\"}");
+
+        // An item in the trash is restored, then updated back.
+        repo.update(SqliteRepositoryItem::with_id(id, "FUMO-0001".to_string(), "{\"name\":\"c\"}".to_string())).await.unwrap();
+        repo.delete(id).await.unwrap();
+        let changed = repo.log().history::<SqliteRepositoryItem>(id).await.unwrap()[3].id;
+        repo.revert::<SqliteRepositoryItem>(id, changed).await.unwrap();
+        assert_eq!(repo.get_by_id(id).await.unwrap().unwrap().metadata(), "{\"name\":\"c\"}");
+
+        // A purged item is added again.
+        repo.delete(id).await.unwrap();
+        repo.purge(id).await.unwrap();
+        repo.revert::<SqliteRepositoryItem>(id, added).await.unwrap();
+        assert_eq!(repo.get_by_id(id).await.unwrap().unwrap().metadata(), "{\"name\":\"a\"}");
+
+        let operations: Vec<_> = repo.log().history::<SqliteRepositoryItem>(id).await.unwrap().iter().map(|x| x.operation).collect();
+        assert_eq!(operations, [
+            AuditOperation::Add, AuditOperation::Update, AuditOperation::Update,
+            AuditOperation::Update, AuditOperation::Delete, AuditOperation::Restore, AuditOperation::Update,
+            AuditOperation::Delete, AuditOperation::Purge, AuditOperation::Add,
+        ]);
+        assert!(matches!(repo.revert::<SqliteRepositoryItem>(id, -1).await, Err(AuditError::EntryNotFound(-1))));
+
+        // Items purged with the trash are recorded one by one.
+        let other = SqliteRepositoryItem::new("FUMO-0002".to_string(), "{}".to_string());
+        repo.add(other.clone()).await.unwrap();
+        repo.delete(id).await.unwrap();
+        repo.delete(other.id()).await.unwrap();
+        assert_eq!(repo.purge_deleted_before(Utc::now() + chrono::Duration::seconds(1)).await.unwrap(), 2);
+        for id in [id, other.id()] {
+            let history = repo.log().history::<SqliteRepositoryItem>(id).await.unwrap();
+            let last = history.last().unwrap();
+            assert_eq!(last.operation, AuditOperation::Purge);
+            assert!(last.before.as_ref().unwrap().deleted_at().is_some());
+        }
+    });
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_audit_failure_rolls_back() {
+    use crate::infra::repository::sqlite::SqliteRepositoryItem;
+
+    let (path, repo) = test_audited("audit-failure");
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let item = SqliteRepositoryItem::new("FUMO-0001".to_string(), "{}".to_string());
+        repo.add(item.clone()).await.unwrap();
+
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch("CREATE TRIGGER audit_fail BEFORE INSERT ON audit_log BEGIN SELECT RAISE(ABORT, 'fail'); END").unwrap();
+        let other = SqliteRepositoryItem::new("FUMO-0002".to_string(), "{}".to_string());
+        assert!(matches!(repo.add(other.clone()).await, Err(AuditError::Repository(_))));
+        assert!(repo.get_by_id(other.id()).await.unwrap().is_none());
+        assert!(repo.delete(item.id()).await.is_err());
+        assert!(repo.get_by_id(item.id()).await.unwrap().is_some());
+
+        conn.execute_batch("DROP TRIGGER audit_fail").unwrap();
+        let history = repo.log().history::<SqliteRepositoryItem>(item.id()).await.unwrap();
+        assert_eq!(history.len(), 1);
+    });
+    fs::remove_file(&path).unwrap();
+}
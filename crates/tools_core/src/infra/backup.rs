@@ -0,0 +1,399 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime, SubsecRound, TimeDelta, Utc};
+use rusqlite::backup::Backup;
+use serde::{Deserialize, Serialize};
+
+use crate::infra::repository::encryption::{self, EncryptionError};
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%3fZ";
+const EXTENSION: &str = "sqlite";
+/// Copied at a time, with a pause in between, so the app can keep writing.
+const PAGES_PER_STEP: i32 = 256;
+const STEP_PAUSE: Duration = Duration::from_millis(10);
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackupError {
+    #[error(transparent)]
+    RusqliteError(#[from] rusqlite::Error),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("Task failed to execute: {0}")]
+    JoinError(#[from] tokio::task::JoinError),
+    #[error(transparent)]
+    EncryptionError(#[from] EncryptionError),
+    #[error("{} is not a backup of this database", .0.display())]
+    NotABackup(PathBuf),
+    #[error("backup {} is damaged: {}", .0.display(), .1)]
+    Damaged(PathBuf, String),
+}
+
+/// A snapshot of a database, named after the database and the time it was taken.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BackupInfo {
+    pub path: PathBuf,
+    pub created_at: DateTime<Utc>,
+    pub size: u64,
+}
+
+/// Which backups to keep. The newest one is always kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// How many of the newest backups to keep.
+    pub keep_last: usize,
+    /// Backups older than this many days are removed even if among the newest.
+    #[serde(default)]
+    pub max_age_days: Option<u32>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self { keep_last: 10, max_age_days: Some(30) }
+    }
+}
+
+impl RetentionPolicy {
+    /// The backups to remove, given newest first.
+    pub fn expired<'a>(&self, backups: &'a [BackupInfo], now: DateTime<Utc>) -> Vec<&'a BackupInfo> {
+        let max_age = self.max_age_days.map(|x| TimeDelta::days(x.into()));
+        backups.iter()
+            .enumerate()
+            .filter(|(i, x)| {
+                *i > 0 && (*i >= self.keep_last || max_age.is_some_and(|max_age| now - x.created_at > max_age))
+            })
+            .map(|(_, x)| x)
+            .collect()
+    }
+}
+
+/// Copy a whole database with the online backup API, which gives a consistent snapshot
+/// even if other connections write to it meanwhile.
+fn copy(from: &rusqlite::Connection, to: &mut rusqlite::Connection) -> rusqlite::Result<()> {
+    Backup::new(from, to)?.run_to_completion(PAGES_PER_STEP, STEP_PAUSE, None)
+}
+
+/// Check that a backup can be opened and is not corrupt.
+fn check(path: &Path, passphrase: Option<&str>) -> Result<(), BackupError> {
+    let conn = encryption::open(path, passphrase)?;
+    let rs: String = conn.query_row("PRAGMA quick_check", [], |row| row.get(0))?;
+    if rs == "ok" { Ok(()) } else { Err(BackupError::Damaged(path.to_path_buf(), rs)) }
+}
+
+/// Timestamped backups of a database kept in a directory.
+///
+/// Backups of an encrypted database are encrypted with the same passphrase, and need the
+/// passphrase they were made with to be restored.
+pub struct SqliteBackup {
+    db_path: PathBuf,
+    dir: PathBuf,
+    passphrase: Option<String>,
+}
+
+impl SqliteBackup {
+    /// # Arguments
+    /// * `db_path` - The database to back up.
+    /// * `dir` - Where the backups are stored, created if needed.
+    pub fn new(db_path: impl Into<PathBuf>, dir: impl Into<PathBuf>) -> Self {
+        Self { db_path: db_path.into(), dir: dir.into(), passphrase: None }
+    }
+
+    /// Unlock an encrypted database and its backups with a SQLCipher passphrase.
+    pub fn with_passphrase(mut self, passphrase: String) -> Self {
+        self.passphrase = Some(passphrase);
+        self
+    }
+
+    /// Backups are named `<database name>-<UTC time>.sqlite`.
+    fn prefix(&self) -> String {
+        let stem = self.db_path.file_stem().unwrap_or_default().to_string_lossy();
+        format!("{}-", stem)
+    }
+
+    fn parse_name(&self, path: &Path) -> Option<DateTime<Utc>> {
+        if path.extension()? != EXTENSION {
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?;
+        let timestamp = stem.strip_prefix(&self.prefix())?;
+        NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok().map(|x| x.and_utc())
+    }
+
+    /// Take a snapshot of the database and check it.
+    pub async fn create(&self) -> Result<BackupInfo, BackupError> {
+        // The name keeps milliseconds, and `list` reads the time back from it.
+        let created_at = Utc::now().trunc_subsecs(3);
+        let name = format!("{}{}.{}", self.prefix(), created_at.format(TIMESTAMP_FORMAT), EXTENSION);
+        let path = self.dir.join(name);
+        let partial = path.with_extension("partial");
+        let db_path = self.db_path.clone();
+        let dir = self.dir.clone();
+        let passphrase = self.passphrase.clone();
+        tokio::task::spawn_blocking(move || -> Result<BackupInfo, BackupError> {
+            fs::create_dir_all(dir)?;
+            let from = encryption::open(&db_path, passphrase.as_deref())?;
+            let mut to = encryption::open(&partial, passphrase.as_deref())?;
+            let rs = copy(&from, &mut to).map_err(BackupError::from)
+                .and_then(|_| check(&partial, passphrase.as_deref()));
+            drop(to);
+            if let Err(e) = rs {
+                let _ = fs::remove_file(&partial);
+                return Err(e);
+            }
+            // Only complete backups get a name that `list` knows.
+            fs::rename(&partial, &path)?;
+            let size = fs::metadata(&path)?.len();
+            Ok(BackupInfo { path, created_at, size })
+        })
+        .await?
+    }
+
+    /// The backups of the database, newest first.
+    pub async fn list(&self) -> Result<Vec<BackupInfo>, BackupError> {
+        let mut rs = Vec::new();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(x) => x,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(rs),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if let Some(created_at) = self.parse_name(&path) {
+                let size = fs::metadata(&path)?.len();
+                rs.push(BackupInfo { path, created_at, size });
+            }
+        }
+        rs.sort_by_key(|x| std::cmp::Reverse(x.created_at));
+        Ok(rs)
+    }
+
+    /// Remove the backups the policy does not keep, returning them.
+    pub async fn prune(&self, policy: &RetentionPolicy) -> Result<Vec<BackupInfo>, BackupError> {
+        let backups = self.list().await?;
+        let expired: Vec<_> = policy.expired(&backups, Utc::now()).into_iter().cloned().collect();
+        for backup in &expired {
+            fs::remove_file(&backup.path)?;
+        }
+        Ok(expired)
+    }
+
+    /// Check that a backup of the database can be opened and is not corrupt.
+    ///
+    /// # Errors
+    /// * `BackupError::NotABackup` if the file is not named as a backup of the database.
+    /// * `BackupError::Damaged` if SQLite finds problems in it.
+    pub async fn verify(&self, path: &Path) -> Result<(), BackupError> {
+        if self.parse_name(path).is_none() {
+            return Err(BackupError::NotABackup(path.to_path_buf()));
+        }
+        let path = path.to_path_buf();
+        let passphrase = self.passphrase.clone();
+        tokio::task::spawn_blocking(move || check(&path, passphrase.as_deref())).await?
+    }
+
+    /// Encrypt the plaintext backups with a passphrase, once the database is encrypted with
+    /// it, so they neither keep a readable copy of it nor need another passphrase to be
+    /// restored. Returns the backups encrypted.
+    ///
+    /// # Errors
+    /// * `BackupError::EncryptionError` if a backup cannot be encrypted, e.g. because
+    ///   SQLite has no SQLCipher. The backups encrypted before it stay encrypted.
+    pub async fn encrypt_plaintext(&self, passphrase: &str) -> Result<Vec<BackupInfo>, BackupError> {
+        let mut rs = Vec::new();
+        for backup in self.list().await? {
+            if encryption::is_encrypted(&backup.path)? {
+                continue;
+            }
+            encryption::encrypt(&backup.path.to_string_lossy(), passphrase).await?;
+            let size = fs::metadata(&backup.path)?.len();
+            rs.push(BackupInfo { size, ..backup });
+        }
+        Ok(rs)
+    }
+
+    /// Change the passphrase of the encrypted backups, once the passphrase of the database
+    /// is changed, so they can still be restored and the old passphrase no longer opens
+    /// them. Backups that the new passphrase already opens are left as they are. Returns
+    /// the backups rekeyed.
+    ///
+    /// # Errors
+    /// * `BackupError::EncryptionError` if a backup cannot be rekeyed, e.g. because
+    ///   neither passphrase opens it. The backups rekeyed before it keep the new passphrase.
+    pub async fn rekey_encrypted(&self, old: &str, new: &str) -> Result<Vec<BackupInfo>, BackupError> {
+        let mut rs = Vec::new();
+        for backup in self.list().await? {
+            if !encryption::is_encrypted(&backup.path)? {
+                continue;
+            }
+            let path = backup.path.to_string_lossy().to_string();
+            match encryption::rekey(&path, old, new).await {
+                Err(EncryptionError::WrongPassphrase) if self.opens(&backup.path, new).await? => continue,
+                result => result?,
+            }
+            rs.push(backup);
+        }
+        Ok(rs)
+    }
+
+    async fn opens(&self, path: &Path, passphrase: &str) -> Result<bool, BackupError> {
+        let path = path.to_path_buf();
+        let passphrase = passphrase.to_string();
+        let rs = tokio::task::spawn_blocking(move || encryption::open(path, Some(&passphrase))).await?;
+        match rs {
+            Ok(_) => Ok(true),
+            Err(EncryptionError::WrongPassphrase) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replace the content of the database with a backup, after verifying it. Other
+    /// connections to the database see the change as a write of another process.
+    pub async fn restore(&self, path: &Path) -> Result<(), BackupError> {
+        self.verify(path).await?;
+        let path = path.to_path_buf();
+        let db_path = self.db_path.clone();
+        let passphrase = self.passphrase.clone();
+        tokio::task::spawn_blocking(move || -> Result<(), BackupError> {
+            let from = encryption::open(&path, passphrase.as_deref())?;
+            let mut to = encryption::open(&db_path, passphrase.as_deref())?;
+            Ok(copy(&from, &mut to)?)
+        })
+        .await?
+    }
+}
+
+#[test]
+fn test_retention_policy() {
+    let now = Utc::now();
+    let backups: Vec<_> = [0, 1, 2, 40, 41]
+        .into_iter()
+        .map(|days| BackupInfo {
+            path: PathBuf::from(format!("{}.sqlite", days)),
+            created_at: now - TimeDelta::days(days),
+            size: 0,
+        })
+        .collect();
+    let names = |policy: RetentionPolicy| -> Vec<_> {
+        policy.expired(&backups, now).iter().map(|x| x.path.to_string_lossy().to_string()).collect()
+    };
+
+    assert_eq!(names(RetentionPolicy { keep_last: 3, max_age_days: None }), ["40.sqlite", "41.sqlite"]);
+    assert_eq!(names(RetentionPolicy { keep_last: 10, max_age_days: Some(30) }), ["40.sqlite", "41.sqlite"]);
+    assert_eq!(names(RetentionPolicy { keep_last: 2, max_age_days: Some(30) }), ["2.sqlite", "40.sqlite", "41.sqlite"]);
+    // The newest backup is kept however old.
+    assert_eq!(names(RetentionPolicy { keep_last: 0, max_age_days: Some(0) }).len(), 4);
+    assert_eq!(names(RetentionPolicy { keep_last: 0, max_age_days: None }).len(), 4);
+}
+
+#[cfg(test)]
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tools_core-backup-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_backup_round_trip() {
+    let dir = test_dir("round-trip");
+    let db_path = dir.join("items.sqlite");
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    conn.execute_batch("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1);").unwrap();
+    let count = || conn.query_row("SELECT count(*) FROM t", [], |row| row.get::<_, i64>(0)).unwrap();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let backup = SqliteBackup::new(&db_path, dir.join("backups"));
+        assert!(backup.list().await.unwrap().is_empty());
+        let info = backup.create().await.unwrap();
+        assert_eq!(backup.list().await.unwrap(), std::slice::from_ref(&info));
+        backup.verify(&info.path).await.unwrap();
+
+        conn.execute("INSERT INTO t VALUES (2)", []).unwrap();
+        assert_eq!(count(), 2);
+        backup.restore(&info.path).await.unwrap();
+        assert_eq!(count(), 1);
+
+        // Only files named as backups of this database are verified or restored.
+        let other = SqliteBackup::new(dir.join("other.sqlite"), dir.join("backups"));
+        assert!(other.list().await.unwrap().is_empty());
+        assert!(matches!(other.verify(&info.path).await, Err(BackupError::NotABackup(_))));
+        assert!(matches!(backup.restore(&db_path).await, Err(BackupError::NotABackup(_))));
+        assert_eq!(count(), 1);
+
+        // Backups of a plaintext database are encrypted once it is.
+        let rs = backup.encrypt_plaintext("secret").await;
+        if encryption::is_supported() {
+            assert_eq!(rs.unwrap().len(), 1);
+            assert!(encryption::is_encrypted(&info.path).unwrap());
+            backup.with_passphrase("secret".to_string()).verify(&info.path).await.unwrap();
+        } else {
+            assert!(matches!(rs, Err(BackupError::EncryptionError(EncryptionError::Unsupported))));
+            assert!(!encryption::is_encrypted(&info.path).unwrap());
+        }
+    });
+    drop(conn);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_backup_damaged() {
+    let dir = test_dir("damaged");
+    let db_path = dir.join("items.sqlite");
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    conn.execute_batch(
+        "PRAGMA page_size = 4096;
+        CREATE TABLE t (x BLOB);
+        WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 20)
+        INSERT INTO t SELECT randomblob(1000) FROM n;",
+    ).unwrap();
+    drop(conn);
+    // Overwrite a page of the table, the schema on the first page still reads fine.
+    let mut bytes = fs::read(&db_path).unwrap();
+    bytes[2 * 4096..3 * 4096].fill(0xff);
+    fs::write(&db_path, bytes).unwrap();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let backup = SqliteBackup::new(&db_path, dir.join("backups"));
+        assert!(matches!(backup.create().await, Err(BackupError::Damaged(..))));
+        // The partial copy is removed.
+        assert_eq!(fs::read_dir(dir.join("backups")).unwrap().count(), 0);
+    });
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "sqlcipher")]
+#[test]
+fn test_backup_rekey() {
+    let dir = test_dir("rekey");
+    let db_path = dir.join("items.sqlite");
+    let conn = encryption::open(&db_path, Some("old")).unwrap();
+    conn.execute_batch("CREATE TABLE t (x INTEGER); INSERT INTO t VALUES (1);").unwrap();
+    drop(conn);
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let info = SqliteBackup::new(&db_path, dir.join("backups"))
+            .with_passphrase("old".to_string())
+            .create().await.unwrap();
+        encryption::rekey(&db_path.to_string_lossy(), "old", "new").await.unwrap();
+
+        let backup = SqliteBackup::new(&db_path, dir.join("backups")).with_passphrase("new".to_string());
+        assert_eq!(backup.rekey_encrypted("old", "new").await.unwrap(), std::slice::from_ref(&info));
+        backup.verify(&info.path).await.unwrap();
+        backup.restore(&info.path).await.unwrap();
+        assert!(matches!(
+            SqliteBackup::new(&db_path, dir.join("backups")).with_passphrase("old".to_string()).verify(&info.path).await,
+            Err(BackupError::EncryptionError(EncryptionError::WrongPassphrase))
+        ));
+        // Rekeying again leaves the backups as they are.
+        assert!(backup.rekey_encrypted("old", "new").await.unwrap().is_empty());
+        assert!(matches!(
+            backup.rekey_encrypted("other", "another").await,
+            Err(BackupError::EncryptionError(EncryptionError::WrongPassphrase))
+        ));
+    });
+    fs::remove_dir_all(&dir).unwrap();
+}
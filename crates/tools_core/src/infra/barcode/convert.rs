@@ -0,0 +1,179 @@
+use std::str::FromStr;
+
+use log::warn;
+
+use super::{options::CropRect, BarcodeError};
+
+/// A luma image, one byte per pixel, row-major.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Luma {
+    pub luma: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Luma {
+    /// Cut out a region of the image.
+    ///
+    /// # Errors
+    /// * `BarcodeError::InvalidArgument` if the region is empty or not inside the image.
+    pub fn crop(self, rect: &CropRect) -> Result<Luma, BarcodeError> {
+        let inside = rect.left.checked_add(rect.width).is_some_and(|x| x <= self.width)
+            && rect.top.checked_add(rect.height).is_some_and(|x| x <= self.height);
+        if rect.width == 0 || rect.height == 0 || !inside {
+            warn!("Crop region {:?} is not inside the {}x{} image", rect, self.width, self.height);
+            return Err(BarcodeError::InvalidArgument(format!(
+                "Crop region {:?} is not inside the {}x{} image",
+                rect, self.width, self.height
+            )));
+        }
+        let (left, width) = (rect.left as usize, rect.width as usize);
+        let luma = self.luma.chunks_exact(self.width as usize)
+            .skip(rect.top as usize)
+            .take(rect.height as usize)
+            .flat_map(|row| &row[left..left + width])
+            .copied()
+            .collect();
+        Ok(Luma { luma, width: rect.width, height: rect.height })
+    }
+}
+
+/// The pixel layout of a raw camera frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Luma,
+    Rgba,
+    Nv12,
+}
+
+impl FromStr for PixelFormat {
+    type Err = BarcodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "luma" => Ok(PixelFormat::Luma),
+            "rgba" => Ok(PixelFormat::Rgba),
+            "nv12" => Ok(PixelFormat::Nv12),
+            _ => {
+                warn!("Unknown pixel format: {}", s);
+                Err(BarcodeError::InvalidArgument(format!("Unknown pixel format: {}", s)))
+            }
+        }
+    }
+}
+
+/// A raw camera frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub format: PixelFormat,
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Frame {
+    /// Convert the frame to luma.
+    ///
+    /// # Errors
+    /// * `BarcodeError::InvalidArgument` if the buffer length does not match the dimensions.
+    pub fn into_luma(self) -> Result<Luma, BarcodeError> {
+        match self.format {
+            PixelFormat::Luma => {
+                check_len("luma", self.data.len(), self.width as usize * self.height as usize, self.width, self.height)?;
+                Ok(Luma { luma: self.data, width: self.width, height: self.height })
+            }
+            PixelFormat::Rgba => rgba_to_luma(&self.data, self.width, self.height),
+            PixelFormat::Nv12 => nv12_to_luma(self.data, self.width, self.height),
+        }
+    }
+}
+
+fn check_len(kind: &str, len: usize, expected: usize, width: u32, height: u32) -> Result<(), BarcodeError> {
+    if len != expected {
+        warn!("Unexpected {} buffer length for {}x{}: expected {}, got {}", kind, width, height, expected, len);
+        return Err(BarcodeError::InvalidArgument(format!(
+            "Unexpected {} buffer length for {}x{}: expected {}, got {}",
+            kind, width, height, expected, len
+        )));
+    }
+    Ok(())
+}
+
+/// BT.601 luma of a pixel composited over white, so transparent backgrounds read as light.
+fn rgba_pixel_to_luma([r, g, b, a]: [u8; 4]) -> u8 {
+    let luma = (299 * r as u32 + 587 * g as u32 + 114 * b as u32 + 500) / 1000;
+    ((luma * a as u32 + 255 * (255 - a as u32) + 127) / 255) as u8
+}
+
+/// Convert an RGBA buffer, 4 bytes per pixel as from a canvas, to luma.
+///
+/// # Errors
+/// * `BarcodeError::InvalidArgument` if the buffer length does not match the dimensions.
+pub fn rgba_to_luma(rgba: &[u8], width: u32, height: u32) -> Result<Luma, BarcodeError> {
+    check_len("RGBA", rgba.len(), width as usize * height as usize * 4, width, height)?;
+    let luma = rgba.chunks_exact(4)
+        .map(|x| rgba_pixel_to_luma([x[0], x[1], x[2], x[3]]))
+        .collect();
+    Ok(Luma { luma, width, height })
+}
+
+/// Take the luma of an NV12 buffer, a Y plane followed by an interleaved half-resolution UV plane.
+///
+/// # Errors
+/// * `BarcodeError::InvalidArgument` if the buffer length does not match the dimensions.
+pub fn nv12_to_luma(mut nv12: Vec<u8>, width: u32, height: u32) -> Result<Luma, BarcodeError> {
+    let y_len = width as usize * height as usize;
+    let uv_len = width.div_ceil(2) as usize * height.div_ceil(2) as usize * 2;
+    check_len("NV12", nv12.len(), y_len + uv_len, width, height)?;
+    nv12.truncate(y_len);
+    Ok(Luma { luma: nv12, width, height })
+}
+
+/// Decode a PNG, JPEG or WebP image to luma.
+///
+/// # Errors
+/// * `BarcodeError::ImageError` if the format is not supported or the image is corrupt.
+pub fn image_to_luma(bytes: &[u8]) -> Result<Luma, BarcodeError> {
+    let image = image::load_from_memory(bytes)
+        .inspect_err(|e| warn!("image decode error: {e}"))?
+        .into_rgba8();
+    let (width, height) = image.dimensions();
+    rgba_to_luma(image.as_raw(), width, height)
+}
+
+#[test]
+fn test_rgba_to_luma() {
+    let rgba = [0, 0, 0, 255, 255, 255, 255, 255, 0, 0, 0, 0, 255, 0, 0, 255, 0, 0, 0, 128];
+    let rs = rgba_to_luma(&rgba, 5, 1).unwrap();
+    assert_eq!(rs.luma, [0, 255, 255, 76, 127]);
+    assert!(rgba_to_luma(&rgba, 2, 2).is_err());
+}
+
+#[test]
+fn test_nv12_to_luma() {
+    let mut nv12 = (0..15).collect::<Vec<u8>>();
+    nv12.extend([128; 3 * 2 * 2]);
+    let rs = nv12_to_luma(nv12.clone(), 5, 3).unwrap();
+    assert_eq!(rs.luma, (0..15).collect::<Vec<u8>>());
+    assert!(nv12_to_luma(nv12, 4, 4).is_err());
+}
+
+#[test]
+fn test_luma_crop() {
+    let image = Luma { luma: (0..12).collect(), width: 4, height: 3 };
+    let rs = image.clone().crop(&CropRect { left: 1, top: 1, width: 2, height: 2 }).unwrap();
+    assert_eq!(rs, Luma { luma: vec![5, 6, 9, 10], width: 2, height: 2 });
+    assert!(image.clone().crop(&CropRect { left: 3, top: 0, width: 2, height: 1 }).is_err());
+    assert!(image.crop(&CropRect { left: 0, top: 0, width: 0, height: 1 }).is_err());
+}
+
+#[test]
+fn test_image_to_luma() {
+    use super::encode::{encode, EncodeOptions, Symbology};
+
+    let symbol = encode("1", Symbology::QrCode, &EncodeOptions::default()).unwrap();
+    let rs = image_to_luma(&symbol.to_png().unwrap()).unwrap();
+    assert_eq!((rs.width, rs.height), (symbol.width(), symbol.height()));
+    assert_eq!(rs.luma, symbol.to_bitmap().pixels);
+    assert!(image_to_luma(b"not an image").is_err());
+}
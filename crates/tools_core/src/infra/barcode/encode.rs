@@ -0,0 +1,352 @@
+use std::fmt::Write as _;
+use std::io::Cursor;
+
+use log::warn;
+use rxing::{BarcodeFormat, EncodeHints, MultiFormatWriter, Writer};
+use serde::{Deserialize, Serialize};
+
+use super::BarcodeError;
+
+/// The largest image width or height in pixels, which keeps a bitmap under 256 MiB.
+const IMAGE_SIDE_MAX: u32 = 16384;
+
+/// A barcode symbology that can be generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Symbology {
+    QrCode,
+    Code128,
+    Ean13,
+    DataMatrix,
+}
+
+impl Symbology {
+    fn barcode_format(self) -> BarcodeFormat {
+        match self {
+            Symbology::QrCode => BarcodeFormat::QR_CODE,
+            Symbology::Code128 => BarcodeFormat::CODE_128,
+            Symbology::Ean13 => BarcodeFormat::EAN_13,
+            Symbology::DataMatrix => BarcodeFormat::DATA_MATRIX,
+        }
+    }
+
+    fn is_linear(self) -> bool {
+        matches!(self, Symbology::Code128 | Symbology::Ean13)
+    }
+
+    /// The minimum quiet zone in modules required by the symbology.
+    fn default_quiet_zone(self) -> u32 {
+        match self {
+            Symbology::QrCode => 4,
+            Symbology::Code128 => 10,
+            Symbology::Ean13 => 11,
+            Symbology::DataMatrix => 1,
+        }
+    }
+}
+
+/// QR code error correction level, from about 7% (`L`) to 30% (`H`) of codewords recoverable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ErrorCorrection {
+    #[default]
+    L,
+    M,
+    Q,
+    H,
+}
+
+impl ErrorCorrection {
+    fn as_str(self) -> &'static str {
+        match self {
+            ErrorCorrection::L => "L",
+            ErrorCorrection::M => "M",
+            ErrorCorrection::Q => "Q",
+            ErrorCorrection::H => "H",
+        }
+    }
+}
+
+/// Encoded image file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    Png,
+    Svg,
+}
+
+/// Options for generating a barcode.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncodeOptions {
+    /// Only used by QR codes, Data Matrix always uses ECC 200.
+    pub error_correction: ErrorCorrection,
+    /// The quiet zone in modules, on every side of 2D symbols and on the left and right of 1D barcodes.
+    /// `None` uses the minimum required by the symbology.
+    pub quiet_zone: Option<u32>,
+    /// The size of a module in pixels.
+    pub module_size: u32,
+    /// The height of 1D barcodes in modules, ignored for 2D symbols.
+    pub bar_height: u32,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            error_correction: ErrorCorrection::L,
+            quiet_zone: None,
+            module_size: 4,
+            bar_height: 50,
+        }
+    }
+}
+
+/// A generated barcode as a grid of modules, including the quiet zone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    columns: u32,
+    rows: u32,
+    /// Row-major, `true` for dark modules.
+    modules: Vec<bool>,
+    module_width: u32,
+    module_height: u32,
+}
+
+/// An 8-bit grayscale image, row-major, 0 for black and 255 for white.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Symbol {
+    /// The image width in pixels.
+    pub fn width(&self) -> u32 {
+        self.columns * self.module_width
+    }
+
+    /// The image height in pixels.
+    pub fn height(&self) -> u32 {
+        self.rows * self.module_height
+    }
+
+    /// The number of modules per row.
+    pub fn columns(&self) -> u32 {
+        self.columns
+    }
+
+    /// The number of module rows, 1 for 1D barcodes.
+    pub fn rows(&self) -> u32 {
+        self.rows
+    }
+
+    /// Whether the module at the given column and row is dark.
+    pub fn is_dark(&self, column: u32, row: u32) -> bool {
+        self.modules[(row * self.columns + column) as usize]
+    }
+
+    /// Render the symbol to a grayscale bitmap.
+    pub fn to_bitmap(&self) -> Bitmap {
+        let (width, height) = (self.width(), self.height());
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            let row = y / self.module_height;
+            pixels.extend((0..width).map(|x| if self.is_dark(x / self.module_width, row) { 0 } else { 255 }));
+        }
+        Bitmap { width, height, pixels }
+    }
+
+    /// Render the symbol to a PNG image.
+    ///
+    /// # Errors
+    /// * `BarcodeError::ImageError` if the image cannot be encoded.
+    pub fn to_png(&self) -> Result<Vec<u8>, BarcodeError> {
+        let bitmap = self.to_bitmap();
+        let image = image::GrayImage::from_raw(bitmap.width, bitmap.height, bitmap.pixels)
+            .expect("bitmap size matches its dimensions");
+        let mut png = Vec::new();
+        image.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .inspect_err(|e| warn!("PNG encode error: {e}"))?;
+        Ok(png)
+    }
+
+    /// Render the symbol to an SVG document, one path of dark modules on a white background.
+    pub fn to_svg(&self) -> String {
+        let (width, height) = (self.width(), self.height());
+        let mut path = String::new();
+        for row in 0..self.rows {
+            let mut column = 0;
+            while column < self.columns {
+                if !self.is_dark(column, row) {
+                    column += 1;
+                    continue;
+                }
+                let start = column;
+                while column < self.columns && self.is_dark(column, row) {
+                    column += 1;
+                }
+                let run = (column - start) * self.module_width;
+                write!(
+                    path, "M{},{}h{}v{}h-{}z",
+                    start * self.module_width, row * self.module_height, run, self.module_height, run
+                ).unwrap();
+            }
+        }
+        format!(
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" shape-rendering="crispEdges">"#,
+                r##"<rect width="{w}" height="{h}" fill="#fff"/><path d="{d}" fill="#000"/></svg>"##
+            ),
+            w = width, h = height, d = path
+        )
+    }
+
+    /// Render the symbol to an image file.
+    ///
+    /// # Errors
+    /// * `BarcodeError::ImageError` if the image cannot be encoded.
+    pub fn to_image(&self, format: ImageFormat) -> Result<Vec<u8>, BarcodeError> {
+        match format {
+            ImageFormat::Png => self.to_png(),
+            ImageFormat::Svg => Ok(self.to_svg().into_bytes()),
+        }
+    }
+}
+
+/// Generate a barcode.
+///
+/// # Arguments
+/// * `contents` - The text to encode. EAN-13 takes 12 digits, or 13 with a valid check digit.
+/// * `symbology` - The barcode symbology.
+/// * `options` - Error correction, quiet zone and size options.
+///
+/// # Errors
+/// * `BarcodeError::InvalidArgument` if the module size or bar height is zero, or the image would be
+///   wider or higher than 16384 pixels.
+/// * `BarcodeError::RxingException` if the contents cannot be encoded in the symbology.
+pub fn encode(contents: &str, symbology: Symbology, options: &EncodeOptions) -> Result<Symbol, BarcodeError> {
+    let invalid_size = || {
+        warn!(
+            "Invalid barcode size: module size {}, bar height {}, quiet zone {:?}",
+            options.module_size, options.bar_height, options.quiet_zone
+        );
+        BarcodeError::InvalidArgument(format!(
+            "Invalid barcode size: module size {}, bar height {}, quiet zone {:?}",
+            options.module_size, options.bar_height, options.quiet_zone
+        ))
+    };
+    if options.module_size == 0 || (symbology.is_linear() && options.bar_height == 0) {
+        return Err(invalid_size());
+    }
+
+    // Encode without margins at one pixel per module, and lay out the quiet zone and size here,
+    // as the rxing writers measure them differently for each symbology.
+    let hints = EncodeHints {
+        Margin: Some("0".to_string()),
+        ErrorCorrection: (symbology == Symbology::QrCode).then(|| options.error_correction.as_str().to_string()),
+        ..Default::default()
+    };
+    let matrix = MultiFormatWriter.encode_with_hints(contents, &symbology.barcode_format(), 0, 0, &hints)
+        .inspect_err(|e| warn!("barcode encode error: {e}"))?;
+
+    let quiet_zone = options.quiet_zone.unwrap_or_else(|| symbology.default_quiet_zone());
+    let (vertical_quiet_zone, module_height) = if symbology.is_linear() {
+        (0, options.bar_height.checked_mul(options.module_size).ok_or_else(invalid_size)?)
+    } else {
+        (quiet_zone, options.module_size)
+    };
+    // Checked here so that the image size never overflows when the symbol is rendered.
+    let side = |len: u32, quiet_zone: u32, module_size: u32| {
+        let modules = quiet_zone.checked_mul(2).and_then(|x| x.checked_add(len))?;
+        modules.checked_mul(module_size).filter(|&x| x <= IMAGE_SIDE_MAX).map(|_| modules)
+    };
+    let columns = side(matrix.getWidth(), quiet_zone, options.module_size).ok_or_else(invalid_size)?;
+    let rows = side(matrix.getHeight(), vertical_quiet_zone, module_height).ok_or_else(invalid_size)?;
+    let mut modules = vec![false; (columns * rows) as usize];
+    for y in 0..matrix.getHeight() {
+        for x in 0..matrix.getWidth() {
+            modules[((y + vertical_quiet_zone) * columns + x + quiet_zone) as usize] = matrix.get(x, y);
+        }
+    }
+
+    Ok(Symbol { columns, rows, modules, module_width: options.module_size, module_height })
+}
+
+/// Generate a barcode image.
+///
+/// # Arguments
+/// * `contents` - The text to encode.
+/// * `symbology` - The barcode symbology.
+/// * `options` - Error correction, quiet zone and size options.
+/// * `format` - The image file format.
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` with the image file contents.
+/// * `Err(BarcodeError)` if the contents cannot be encoded or the image cannot be rendered.
+pub async fn encode_image(
+    contents: String,
+    symbology: Symbology,
+    options: EncodeOptions,
+    format: ImageFormat,
+) -> Result<Vec<u8>, BarcodeError> {
+    tokio::task::spawn_blocking(move || encode(&contents, symbology, &options)?.to_image(format))
+        .await
+        .inspect_err(|e| warn!("barcode encode task join error: {e}"))?
+}
+
+#[test]
+fn test_encode_roundtrip() {
+    let cases = [
+        (Symbology::QrCode, "9f3c1a52-7d0e-4c1b-8a2f-0b6c5e9d4a11"),
+        (Symbology::Code128, "FUMO-0042"),
+        (Symbology::Ean13, "4901234567894"),
+        (Symbology::DataMatrix, "4901234567894"),
+    ];
+    for (symbology, contents) in cases {
+        let options = EncodeOptions { error_correction: ErrorCorrection::H, ..Default::default() };
+        let bitmap = encode(contents, symbology, &options).unwrap().to_bitmap();
+        let mut hints = rxing::DecodeHints { TryHarder: Some(true), ..Default::default() };
+        let format = Some(symbology.barcode_format());
+        let rs = rxing::helpers::detect_in_luma_with_hints(bitmap.pixels, bitmap.width, bitmap.height, format, &mut hints)
+            .unwrap();
+        assert_eq!(rs.getText(), contents, "{:?}", symbology);
+    }
+}
+
+#[test]
+fn test_encode_layout() {
+    let options = EncodeOptions { quiet_zone: Some(2), module_size: 3, ..Default::default() };
+    let symbol = encode("1", Symbology::QrCode, &options).unwrap();
+    // A version 1 QR code has 21 modules per side.
+    assert_eq!((symbol.columns, symbol.rows), (25, 25));
+    assert_eq!((symbol.width(), symbol.height()), (75, 75));
+    assert!(!symbol.is_dark(1, 1) && symbol.is_dark(2, 2));
+
+    let bitmap = symbol.to_bitmap();
+    assert_eq!(bitmap.pixels.len(), 75 * 75);
+    assert_eq!((bitmap.pixels[5 * 75 + 5], bitmap.pixels[6 * 75 + 6]), (255, 0));
+    assert!(symbol.to_svg().starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="75" height="75""#));
+    assert_eq!(&symbol.to_png().unwrap()[1..4], b"PNG");
+
+    let symbol = encode("490123456789", Symbology::Ean13, &EncodeOptions::default()).unwrap();
+    assert_eq!((symbol.columns, symbol.rows), (95 + 22, 1));
+    assert_eq!(symbol.height(), 200);
+
+    assert!(encode("abc", Symbology::Ean13, &EncodeOptions::default()).is_err());
+    assert!(encode("abc", Symbology::QrCode, &EncodeOptions { module_size: 0, ..Default::default() }).is_err());
+}
+
+#[test]
+fn test_encode_size_limit() {
+    let invalid = |symbology, options: EncodeOptions| {
+        matches!(encode("490123456789", symbology, &options), Err(BarcodeError::InvalidArgument(_)))
+    };
+    assert!(invalid(Symbology::QrCode, EncodeOptions { module_size: u32::MAX, ..Default::default() }));
+    assert!(invalid(Symbology::QrCode, EncodeOptions { quiet_zone: Some(u32::MAX), ..Default::default() }));
+    assert!(invalid(Symbology::Ean13, EncodeOptions { bar_height: u32::MAX, ..Default::default() }));
+    assert!(invalid(Symbology::Ean13, EncodeOptions { module_size: 1 << 16, bar_height: 1 << 16, ..Default::default() }));
+    assert!(invalid(Symbology::Code128, EncodeOptions { module_size: 200, ..Default::default() }));
+
+    let symbol = encode("490123456789", Symbology::Ean13, &EncodeOptions { bar_height: 4096, ..Default::default() }).unwrap();
+    assert_eq!(symbol.height(), 16384);
+}
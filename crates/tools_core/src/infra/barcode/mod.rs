@@ -0,0 +1,214 @@
+use std::path::PathBuf;
+
+use log::warn;
+use rxing::{BarcodeFormat, RXingResult, RXingResultMetadataType, RXingResultMetadataValue};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::sku::{expand_upc_e, normalize_product_code};
+
+use self::convert::Luma;
+use self::options::ScanOptions;
+use self::preprocess::Strategy;
+
+pub mod convert;
+pub mod encode;
+pub mod options;
+pub mod preprocess;
+pub mod session;
+pub mod worker;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BarcodeError {
+    #[error(transparent)]
+    RxingException(#[from] rxing::Exceptions),
+    #[error(transparent)]
+    TaskJoinError(#[from] tokio::task::JoinError),
+    #[error(transparent)]
+    ImageError(#[from] image::ImageError),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("barcode decoder panicked")]
+    DecoderPanicked,
+    #[error("barcode not found")]
+    NotFound,
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+}
+
+/// A point in image pixel coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BarcodePoint {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// A barcode decoded from an image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecodedBarcode {
+    pub text: String,
+    pub raw_bytes: Vec<u8>,
+    /// The barcode format as named by rxing, e.g. `qrcode` or `ean 13`.
+    pub format: String,
+    /// The corners of the barcode, or the ends of the scan line for 1D barcodes.
+    pub points: Vec<BarcodePoint>,
+    pub error_correction_level: Option<String>,
+    /// The normalized SKU for EAN, UPC and ITF product codes, see [`normalize_product_code`].
+    pub sku: Option<String>,
+    /// How the image was prepared for the decoder to find this barcode.
+    #[serde(default)]
+    pub strategy: Strategy,
+}
+
+impl From<&RXingResult> for DecodedBarcode {
+    fn from(rs: &RXingResult) -> Self {
+        let error_correction_level = match rs.getRXingResultMetadata().get(&RXingResultMetadataType::ERROR_CORRECTION_LEVEL) {
+            Some(RXingResultMetadataValue::ErrorCorrectionLevel(x)) => Some(x.clone()),
+            _ => None,
+        };
+        let sku = match rs.getBarcodeFormat() {
+            BarcodeFormat::EAN_8 | BarcodeFormat::EAN_13 | BarcodeFormat::UPC_A | BarcodeFormat::ITF => normalize_product_code(rs.getText()).ok(),
+            BarcodeFormat::UPC_E => expand_upc_e(rs.getText()).and_then(|x| normalize_product_code(&x)).ok(),
+            _ => None,
+        };
+        Self {
+            text: rs.getText().to_string(),
+            raw_bytes: rs.getRawBytes().to_vec(),
+            format: rs.getBarcodeFormat().to_string(),
+            points: rs.getPoints().iter().map(|p| BarcodePoint { x: p.x, y: p.y }).collect(),
+            error_correction_level,
+            sku,
+            strategy: Strategy::Original,
+        }
+    }
+}
+
+fn detect_all(luma: Luma, options: &ScanOptions) -> Result<Vec<DecodedBarcode>, BarcodeError> {
+    let luma = match &options.crop {
+        Some(rect) => luma.crop(rect)?,
+        None => luma,
+    };
+    let (left, top) = options.crop.map_or((0.0, 0.0), |x| (x.left as f32, x.top as f32));
+    let strategies = std::iter::once(Strategy::Original)
+        .chain(options.preprocess.iter().copied().filter(|x| *x != Strategy::Original));
+    for strategy in strategies {
+        let image = strategy.apply(&luma);
+        let rs = rxing::helpers::detect_multiple_in_luma_with_hints(image.luma, image.width, image.height, &mut options.hints());
+        if let Err(rxing::Exceptions::NotFoundException(_)) = rs {
+            continue;
+        }
+        return Ok(rs?.iter()
+            .map(DecodedBarcode::from)
+            .map(|mut x| {
+                x.strategy = strategy;
+                x.points.iter_mut().for_each(|p| {
+                    *p = strategy.unmap(*p, luma.height);
+                    p.x += left;
+                    p.y += top;
+                });
+                x
+            })
+            .collect());
+    }
+    Ok(vec![])
+}
+
+/// Convert an image to luma and decode it on the blocking thread pool.
+async fn decode_all_with<F>(to_luma: F, options: ScanOptions) -> Result<Vec<DecodedBarcode>, BarcodeError>
+where
+    F: FnOnce() -> Result<Luma, BarcodeError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || detect_all(to_luma()?, &options))
+        .await
+        .inspect_err(|e| warn!("barcode decode task join error: {e}"))?
+}
+
+/// Decode a barcode from a luma image. Only the first result is returned.
+///
+/// # Arguments
+/// * `luma` - A vector of u8 representing the luma image.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+///
+/// # Returns
+/// * `Ok(Some(String))` if a barcode is found and decoded successfully.
+/// * `Ok(None)` if no barcode is found.
+/// * `Err(BarcodeError)` if an error occurs during decoding.
+pub async fn decode_text_from_luma(luma: Vec<u8>, width: u32, height: u32) -> Result<Option<String>, BarcodeError> {
+    let rs = decode_all_with(move || Ok(Luma { luma, width, height }), ScanOptions::default()).await?;
+    Ok(rs.into_iter().next().map(|x| x.text))
+}
+
+/// Decode all barcodes from a luma image.
+///
+/// # Arguments
+/// * `luma` - A vector of u8 representing the luma image.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `options` - The formats, hints and region to scan.
+///
+/// # Returns
+/// * `Ok(Vec<DecodedBarcode>)` with every barcode found, empty if none is found.
+/// * `Err(BarcodeError)` if an error occurs during decoding.
+pub async fn decode_all_from_luma(luma: Vec<u8>, width: u32, height: u32, options: ScanOptions) -> Result<Vec<DecodedBarcode>, BarcodeError> {
+    decode_all_with(move || Ok(Luma { luma, width, height }), options).await
+}
+
+/// Decode all barcodes from an RGBA image, such as canvas image data.
+///
+/// # Arguments
+/// * `rgba` - A vector of u8 with 4 bytes per pixel. Transparent pixels are read as white.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `options` - The formats, hints and region to scan.
+///
+/// # Returns
+/// * `Ok(Vec<DecodedBarcode>)` with every barcode found, empty if none is found.
+/// * `Err(BarcodeError)` if the buffer does not match the dimensions or an error occurs during decoding.
+pub async fn decode_all_from_rgba(rgba: Vec<u8>, width: u32, height: u32, options: ScanOptions) -> Result<Vec<DecodedBarcode>, BarcodeError> {
+    decode_all_with(move || convert::rgba_to_luma(&rgba, width, height), options).await
+}
+
+/// Decode all barcodes from an NV12 camera frame.
+///
+/// # Arguments
+/// * `nv12` - A vector of u8 with the Y plane followed by the interleaved UV plane.
+/// * `width` - The width of the image.
+/// * `height` - The height of the image.
+/// * `options` - The formats, hints and region to scan.
+///
+/// # Returns
+/// * `Ok(Vec<DecodedBarcode>)` with every barcode found, empty if none is found.
+/// * `Err(BarcodeError)` if the buffer does not match the dimensions or an error occurs during decoding.
+pub async fn decode_all_from_nv12(nv12: Vec<u8>, width: u32, height: u32, options: ScanOptions) -> Result<Vec<DecodedBarcode>, BarcodeError> {
+    decode_all_with(move || convert::nv12_to_luma(nv12, width, height), options).await
+}
+
+/// Decode all barcodes from an encoded image file.
+///
+/// # Arguments
+/// * `bytes` - The contents of a PNG, JPEG or WebP file.
+/// * `options` - The formats, hints and region to scan.
+///
+/// # Returns
+/// * `Ok(Vec<DecodedBarcode>)` with every barcode found, empty if none is found.
+/// * `Err(BarcodeError)` if the image cannot be decoded or an error occurs during decoding.
+pub async fn decode_all_from_image(bytes: Vec<u8>, options: ScanOptions) -> Result<Vec<DecodedBarcode>, BarcodeError> {
+    decode_all_with(move || convert::image_to_luma(&bytes), options).await
+}
+
+/// Decode all barcodes from an image file on disk.
+///
+/// # Arguments
+/// * `path` - The path of a PNG, JPEG or WebP file.
+/// * `options` - The formats, hints and region to scan.
+///
+/// # Returns
+/// * `Ok(Vec<DecodedBarcode>)` with every barcode found, empty if none is found.
+/// * `Err(BarcodeError)` if the file cannot be read, the image cannot be decoded or an error occurs during decoding.
+pub async fn decode_all_from_image_file(path: PathBuf, options: ScanOptions) -> Result<Vec<DecodedBarcode>, BarcodeError> {
+    decode_all_with(move || {
+        let bytes = std::fs::read(&path)
+            .inspect_err(|e| warn!("Failed to read image file {}: {}", path.display(), e))?;
+        convert::image_to_luma(&bytes)
+    }, options).await
+}
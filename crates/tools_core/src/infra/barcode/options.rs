@@ -0,0 +1,146 @@
+use rxing::{BarcodeFormat, DecodeHints};
+use serde::{Deserialize, Serialize};
+
+use super::preprocess::Strategy;
+
+/// A barcode format that can be scanned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanFormat {
+    Aztec,
+    Codabar,
+    Code39,
+    Code93,
+    Code128,
+    DataMatrix,
+    Ean8,
+    Ean13,
+    Itf,
+    Maxicode,
+    Pdf417,
+    QrCode,
+    MicroQrCode,
+    RectangularMicroQrCode,
+    Rss14,
+    RssExpanded,
+    Telepen,
+    UpcA,
+    UpcE,
+    DxFilmEdge,
+}
+
+impl ScanFormat {
+    fn barcode_format(self) -> BarcodeFormat {
+        match self {
+            ScanFormat::Aztec => BarcodeFormat::AZTEC,
+            ScanFormat::Codabar => BarcodeFormat::CODABAR,
+            ScanFormat::Code39 => BarcodeFormat::CODE_39,
+            ScanFormat::Code93 => BarcodeFormat::CODE_93,
+            ScanFormat::Code128 => BarcodeFormat::CODE_128,
+            ScanFormat::DataMatrix => BarcodeFormat::DATA_MATRIX,
+            ScanFormat::Ean8 => BarcodeFormat::EAN_8,
+            ScanFormat::Ean13 => BarcodeFormat::EAN_13,
+            ScanFormat::Itf => BarcodeFormat::ITF,
+            ScanFormat::Maxicode => BarcodeFormat::MAXICODE,
+            ScanFormat::Pdf417 => BarcodeFormat::PDF_417,
+            ScanFormat::QrCode => BarcodeFormat::QR_CODE,
+            ScanFormat::MicroQrCode => BarcodeFormat::MICRO_QR_CODE,
+            ScanFormat::RectangularMicroQrCode => BarcodeFormat::RECTANGULAR_MICRO_QR_CODE,
+            ScanFormat::Rss14 => BarcodeFormat::RSS_14,
+            ScanFormat::RssExpanded => BarcodeFormat::RSS_EXPANDED,
+            ScanFormat::Telepen => BarcodeFormat::TELEPEN,
+            ScanFormat::UpcA => BarcodeFormat::UPC_A,
+            ScanFormat::UpcE => BarcodeFormat::UPC_E,
+            ScanFormat::DxFilmEdge => BarcodeFormat::DXFilmEdge,
+        }
+    }
+}
+
+/// A region of an image in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CropRect {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Options for scanning an image.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    /// The formats to look for, empty for every format.
+    pub formats: Vec<ScanFormat>,
+    /// Spend more time on each image for better accuracy.
+    pub try_harder: bool,
+    /// Also look for light codes on a dark background.
+    pub inverted: bool,
+    /// The character set of byte-encoded contents, e.g. `Shift_JIS`. `None` guesses it from the bytes.
+    pub character_set: Option<String>,
+    /// Only scan this region of the image. Barcode points are still in whole-image coordinates.
+    pub crop: Option<CropRect>,
+    /// The strategies to retry with, in order, when nothing is found in the image as given.
+    pub preprocess: Vec<Strategy>,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            formats: vec![],
+            try_harder: true,
+            inverted: false,
+            character_set: None,
+            crop: None,
+            preprocess: vec![],
+        }
+    }
+}
+
+impl ScanOptions {
+    pub(super) fn hints(&self) -> DecodeHints {
+        DecodeHints {
+            PossibleFormats: (!self.formats.is_empty())
+                .then(|| self.formats.iter().map(|x| x.barcode_format()).collect()),
+            TryHarder: Some(self.try_harder),
+            AlsoInverted: self.inverted.then_some(true),
+            CharacterSet: self.character_set.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+#[test]
+fn test_scan_options() {
+    use super::{convert::Luma, detect_all, encode::{encode, EncodeOptions, Symbology}};
+
+    // A QR code at (60, 40) on a white 240x240 image.
+    let bitmap = encode("FUMO-0042", Symbology::QrCode, &EncodeOptions::default()).unwrap().to_bitmap();
+    let (width, height) = (240, 240);
+    let mut luma = vec![255; width * height];
+    for (y, row) in bitmap.pixels.chunks_exact(bitmap.width as usize).enumerate() {
+        let start = (40 + y) * width + 60;
+        luma[start..start + row.len()].copy_from_slice(row);
+    }
+    let image = Luma { luma, width: width as u32, height: height as u32 };
+
+    let rs = detect_all(image.clone(), &ScanOptions::default()).unwrap();
+    assert_eq!(rs.len(), 1);
+    assert_eq!(rs[0].text, "FUMO-0042");
+    let only_ean = ScanOptions { formats: vec![ScanFormat::Ean13], ..Default::default() };
+    assert!(detect_all(image.clone(), &only_ean).unwrap().is_empty());
+
+    // Points found in a crop are moved back to whole-image coordinates.
+    let crop = CropRect { left: 40, top: 20, width: bitmap.width + 40, height: bitmap.height + 40 };
+    let cropped = detect_all(image.clone(), &ScanOptions { crop: Some(crop), ..Default::default() }).unwrap();
+    assert_eq!(cropped.len(), 1);
+    for (a, b) in cropped[0].points.iter().zip(&rs[0].points) {
+        assert!((a.x - b.x).abs() < 1.0 && (a.y - b.y).abs() < 1.0);
+    }
+    let outside = CropRect { left: 200, top: 0, width: 80, height: 80 };
+    assert!(detect_all(image.clone(), &ScanOptions { crop: Some(outside), ..Default::default() }).is_err());
+
+    let inverted = Luma { luma: image.luma.iter().map(|x| 255 - x).collect(), ..image };
+    assert!(detect_all(inverted.clone(), &ScanOptions::default()).unwrap().is_empty());
+    let rs = detect_all(inverted, &ScanOptions { inverted: true, ..Default::default() }).unwrap();
+    assert_eq!(rs.len(), 1);
+}
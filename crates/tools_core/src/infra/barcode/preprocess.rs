@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+
+use super::{convert::Luma, BarcodePoint};
+
+/// Pixels at least this bright are treated as glare.
+const GLARE_THRESHOLD: u8 = 250;
+
+/// A way of preparing an image for the decoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// The image as given.
+    #[default]
+    Original,
+    /// Half the resolution, averaging out sensor noise in dim frames.
+    Downscale,
+    /// Stretch the darkest and brightest percent of pixels to black and white.
+    ContrastStretch,
+    /// Threshold each pixel against the mean of its neighbourhood, for uneven lighting.
+    AdaptiveBinarize,
+    /// Turn the image a quarter clockwise, for 1D barcodes held upright.
+    Rotate90,
+    /// Fill saturated highlights, e.g. reflections on glossy acrylic, with the surrounding brightness.
+    GlareMask,
+}
+
+impl Strategy {
+    /// Prepare an image for the decoder.
+    pub fn apply(self, luma: &Luma) -> Luma {
+        match self {
+            Strategy::Original => luma.clone(),
+            Strategy::Downscale => downscale(luma),
+            Strategy::ContrastStretch => contrast_stretch(luma),
+            Strategy::AdaptiveBinarize => adaptive_binarize(luma),
+            Strategy::Rotate90 => rotate_90(luma),
+            Strategy::GlareMask => glare_mask(luma),
+        }
+    }
+
+    /// Map a point in the prepared image back to an original image `height` pixels high.
+    pub fn unmap(self, point: BarcodePoint, height: u32) -> BarcodePoint {
+        match self {
+            Strategy::Downscale => BarcodePoint { x: point.x * 2.0, y: point.y * 2.0 },
+            Strategy::Rotate90 => BarcodePoint { x: point.y, y: height as f32 - 1.0 - point.x },
+            _ => point,
+        }
+    }
+}
+
+/// Sums over every rectangle of an image in constant time.
+struct IntegralImage {
+    width: usize,
+    /// `(width + 1) * (height + 1)` sums of the pixels above and to the left.
+    sums: Vec<u64>,
+}
+
+impl IntegralImage {
+    fn new(width: usize, height: usize, value: impl Fn(usize, usize) -> u64) -> Self {
+        let stride = width + 1;
+        let mut sums = vec![0; stride * (height + 1)];
+        for y in 0..height {
+            let mut row = 0;
+            for x in 0..width {
+                row += value(x, y);
+                sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row;
+            }
+        }
+        Self { width, sums }
+    }
+
+    /// The sum over `x0..x1`, `y0..y1`.
+    fn sum(&self, x0: usize, y0: usize, x1: usize, y1: usize) -> u64 {
+        let stride = self.width + 1;
+        self.sums[y1 * stride + x1] + self.sums[y0 * stride + x0] - self.sums[y0 * stride + x1] - self.sums[y1 * stride + x0]
+    }
+}
+
+/// The half-width of the neighbourhood used by the local filters, about 1/16 of the image.
+fn window_radius(luma: &Luma) -> usize {
+    (luma.width.max(luma.height) as usize / 32).max(4)
+}
+
+/// Apply a filter to every pixel given the bounds of its neighbourhood.
+fn map_window(luma: &Luma, f: impl Fn(usize, usize, usize, usize, u8) -> u8) -> Luma {
+    let (width, height) = (luma.width as usize, luma.height as usize);
+    let radius = window_radius(luma);
+    let pixels = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let (x0, y0) = (x.saturating_sub(radius), y.saturating_sub(radius));
+            let (x1, y1) = ((x + radius + 1).min(width), (y + radius + 1).min(height));
+            f(x0, y0, x1, y1, luma.luma[y * width + x])
+        })
+        .collect();
+    Luma { luma: pixels, width: luma.width, height: luma.height }
+}
+
+fn downscale(luma: &Luma) -> Luma {
+    let (width, height) = (luma.width as usize / 2, luma.height as usize / 2);
+    let src = luma.width as usize;
+    let pixels = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let i = 2 * y * src + 2 * x;
+            let sum = luma.luma[i] as u32 + luma.luma[i + 1] as u32 + luma.luma[i + src] as u32 + luma.luma[i + src + 1] as u32;
+            ((sum + 2) / 4) as u8
+        })
+        .collect();
+    Luma { luma: pixels, width: width as u32, height: height as u32 }
+}
+
+fn contrast_stretch(luma: &Luma) -> Luma {
+    let mut histogram = [0usize; 256];
+    luma.luma.iter().for_each(|&x| histogram[x as usize] += 1);
+    // The first bin, in the order given, past the clipped percent of pixels.
+    let clip = luma.luma.len() / 100;
+    let percentile = |mut bins: Box<dyn Iterator<Item = usize>>| {
+        let mut count = 0;
+        bins.find(|&x| {
+            count += histogram[x];
+            count > clip
+        }).unwrap_or(0)
+    };
+    let low = percentile(Box::new(0..256));
+    let high = percentile(Box::new((0..256).rev()));
+    if high <= low {
+        return luma.clone();
+    }
+    let pixels = luma.luma.iter()
+        .map(|&x| ((x as usize).clamp(low, high) - low) * 255 / (high - low))
+        .map(|x| x as u8)
+        .collect();
+    Luma { luma: pixels, width: luma.width, height: luma.height }
+}
+
+fn adaptive_binarize(luma: &Luma) -> Luma {
+    let width = luma.width as usize;
+    let integral = IntegralImage::new(width, luma.height as usize, |x, y| luma.luma[y * width + x] as u64);
+    map_window(luma, |x0, y0, x1, y1, pixel| {
+        let mean = integral.sum(x0, y0, x1, y1) / ((x1 - x0) * (y1 - y0)) as u64;
+        // A small bias keeps flat areas white instead of turning noise into modules.
+        if (pixel as u64) + 8 < mean { 0 } else { 255 }
+    })
+}
+
+fn rotate_90(luma: &Luma) -> Luma {
+    let (width, height) = (luma.width as usize, luma.height as usize);
+    let pixels = (0..width)
+        .flat_map(|y| (0..height).map(move |x| (x, y)))
+        .map(|(x, y)| luma.luma[(height - 1 - x) * width + y])
+        .collect();
+    Luma { luma: pixels, width: luma.height, height: luma.width }
+}
+
+fn glare_mask(luma: &Luma) -> Luma {
+    let width = luma.width as usize;
+    let pixel = |x: usize, y: usize| luma.luma[y * width + x];
+    let sums = IntegralImage::new(width, luma.height as usize, |x, y| {
+        let p = pixel(x, y);
+        if p < GLARE_THRESHOLD { p as u64 } else { 0 }
+    });
+    let counts = IntegralImage::new(width, luma.height as usize, |x, y| (pixel(x, y) < GLARE_THRESHOLD) as u64);
+    map_window(luma, |x0, y0, x1, y1, pixel| {
+        if pixel < GLARE_THRESHOLD {
+            return pixel;
+        }
+        match counts.sum(x0, y0, x1, y1) {
+            0 => pixel,
+            count => (sums.sum(x0, y0, x1, y1) / count) as u8,
+        }
+    })
+}
+
+#[test]
+fn test_preprocess_strategies() {
+    let image = Luma { luma: vec![100, 110, 120, 130, 140, 150], width: 3, height: 2 };
+
+    let rs = Strategy::Rotate90.apply(&image);
+    assert_eq!(rs, Luma { luma: vec![130, 100, 140, 110, 150, 120], width: 2, height: 3 });
+    // The top left corner of the rotated image is the bottom left of the original.
+    let p = Strategy::Rotate90.unmap(BarcodePoint { x: 0.0, y: 0.0 }, 2);
+    assert_eq!((p.x, p.y), (0.0, 1.0));
+
+    let rs = Strategy::Downscale.apply(&image);
+    assert_eq!(rs, Luma { luma: vec![120], width: 1, height: 1 });
+
+    let rs = Strategy::ContrastStretch.apply(&image);
+    assert_eq!(rs.luma, [0, 51, 102, 153, 204, 255]);
+
+    let checker = Luma { luma: (0..64).map(|i| if (i + i / 8) % 2 == 0 { 60 } else { 90 }).collect(), width: 8, height: 8 };
+    let rs = Strategy::AdaptiveBinarize.apply(&checker);
+    assert!(rs.luma.iter().zip(&checker.luma).all(|(a, b)| *a == if *b == 60 { 0 } else { 255 }));
+
+    let glare = Luma { luma: vec![40, 255, 80, 255], width: 2, height: 2 };
+    assert_eq!(Strategy::GlareMask.apply(&glare).luma, [40, 60, 80, 60]);
+}
+
+#[test]
+fn test_preprocess_retry() {
+    use super::{detect_all, encode::{encode, EncodeOptions, Symbology}, options::ScanOptions};
+
+    // A Code 128 barcode standing upright is missed without try-harder.
+    let bitmap = encode("FUMO-0042", Symbology::Code128, &EncodeOptions::default()).unwrap().to_bitmap();
+    let image = rotate_90(&Luma { luma: bitmap.pixels, width: bitmap.width, height: bitmap.height });
+    let options = ScanOptions { try_harder: false, ..Default::default() };
+    assert!(detect_all(image.clone(), &options).unwrap().is_empty());
+
+    let options = ScanOptions { preprocess: vec![Strategy::ContrastStretch, Strategy::Rotate90], ..options };
+    let rs = detect_all(image.clone(), &options).unwrap();
+    assert_eq!(rs.len(), 1);
+    assert_eq!(rs[0].text, "FUMO-0042");
+    assert_eq!(rs[0].strategy, Strategy::Rotate90);
+    // The scan line runs down the upright barcode.
+    let [a, b] = [rs[0].points[0], rs[0].points[1]];
+    assert!((a.x - b.x).abs() < 1.0);
+    assert!(a.y.min(b.y) >= 0.0 && a.y.max(b.y) < image.height as f32);
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{options::ScanOptions, DecodedBarcode};
+
+/// How long decoded frames are kept for the frame rate and latency statistics.
+const STATS_WINDOW: Duration = Duration::from_secs(2);
+
+/// Options for a continuous scanning session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionOptions {
+    /// The minimum time in milliseconds before the same code is reported again.
+    pub cooldown_ms: u64,
+    /// The number of consecutive frames a code must be seen in before it is reported.
+    pub confirm_frames: u32,
+    /// The formats, hints and region to scan each frame with.
+    pub scan: ScanOptions,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            cooldown_ms: 2000,
+            confirm_frames: 2,
+            scan: ScanOptions::default(),
+        }
+    }
+}
+
+/// Statistics of a scanning session.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScanStats {
+    /// The number of frames decoded.
+    pub frames: u64,
+    /// The number of frames dropped before they were decoded.
+    pub dropped: u64,
+    /// The number of codes reported.
+    pub reported: u64,
+    /// Decoded frames per second over the last few seconds.
+    pub fps: f64,
+    /// The average decode latency in milliseconds over the last few seconds.
+    pub latency_ms: f64,
+}
+
+#[derive(Debug)]
+struct Seen {
+    /// The number of consecutive frames the code has been seen in.
+    streak: u32,
+    last_frame: u64,
+    reported_at: Option<Instant>,
+}
+
+/// Tracks the codes seen across the frames of a continuous scan, so a code held in front
+/// of the camera is reported once per cooldown instead of once per frame.
+#[derive(Debug)]
+pub struct ScanSession {
+    options: SessionOptions,
+    /// Keyed by format and text.
+    seen: HashMap<(String, String), Seen>,
+    frames: u64,
+    dropped: u64,
+    reported: u64,
+    /// The time each recent frame was decoded at and how long decoding took.
+    window: VecDeque<(Instant, Duration)>,
+}
+
+impl ScanSession {
+    pub fn new(options: SessionOptions) -> Self {
+        Self {
+            options,
+            seen: HashMap::new(),
+            frames: 0,
+            dropped: 0,
+            reported: 0,
+            window: VecDeque::new(),
+        }
+    }
+
+    pub fn options(&self) -> &SessionOptions {
+        &self.options
+    }
+
+    /// Record the barcodes decoded from a frame.
+    ///
+    /// # Arguments
+    /// * `barcodes` - Every barcode decoded from the frame.
+    /// * `latency` - How long decoding the frame took.
+    /// * `now` - When the frame was decoded.
+    ///
+    /// # Returns
+    /// * `Vec<DecodedBarcode>` with the barcodes confirmed by this frame and not reported
+    ///   within the cooldown, empty if there are none.
+    pub fn observe(&mut self, barcodes: Vec<DecodedBarcode>, latency: Duration, now: Instant) -> Vec<DecodedBarcode> {
+        self.frames += 1;
+        self.window.push_back((now, latency));
+        while self.window.front().is_some_and(|(t, _)| now.duration_since(*t) > STATS_WINDOW) {
+            self.window.pop_front();
+        }
+
+        let frame = self.frames;
+        let cooldown = Duration::from_millis(self.options.cooldown_ms);
+        let mut rs = vec![];
+        for barcode in barcodes {
+            let seen = self.seen.entry((barcode.format.clone(), barcode.text.clone()))
+                .or_insert(Seen { streak: 0, last_frame: 0, reported_at: None });
+            if seen.last_frame == frame {
+                // The same code twice in one frame.
+                continue;
+            }
+            seen.streak = if seen.last_frame + 1 == frame { seen.streak + 1 } else { 1 };
+            seen.last_frame = frame;
+            let cooled = seen.reported_at.is_none_or(|t| now.duration_since(t) >= cooldown);
+            if seen.streak >= self.options.confirm_frames && cooled {
+                seen.reported_at = Some(now);
+                rs.push(barcode);
+            }
+        }
+
+        // Forget codes out of view unless they are still cooling down.
+        self.seen.retain(|_, x| x.last_frame == frame || x.reported_at.is_some_and(|t| now.duration_since(t) < cooldown));
+        self.reported += rs.len() as u64;
+        rs
+    }
+
+    /// Record a frame dropped before it was decoded.
+    pub fn drop_frame(&mut self) {
+        self.dropped += 1;
+    }
+
+    pub fn stats(&self) -> ScanStats {
+        let fps = match (self.window.front(), self.window.back()) {
+            (Some((first, _)), Some((last, _))) if last > first => {
+                (self.window.len() - 1) as f64 / last.duration_since(*first).as_secs_f64()
+            }
+            _ => 0.0,
+        };
+        let latency_ms = if self.window.is_empty() {
+            0.0
+        } else {
+            self.window.iter().map(|(_, x)| x.as_secs_f64() * 1000.0).sum::<f64>() / self.window.len() as f64
+        };
+        ScanStats {
+            frames: self.frames,
+            dropped: self.dropped,
+            reported: self.reported,
+            fps,
+            latency_ms,
+        }
+    }
+}
+
+#[test]
+fn test_scan_session_confirm_and_cooldown() {
+    let barcode = |text: &str| DecodedBarcode {
+        text: text.to_string(),
+        raw_bytes: vec![],
+        format: "qrcode".to_string(),
+        points: vec![],
+        error_correction_level: None,
+        sku: None,
+        strategy: Default::default(),
+    };
+    let mut session = ScanSession::new(SessionOptions { cooldown_ms: 1000, confirm_frames: 2, ..Default::default() });
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    let latency = Duration::from_millis(10);
+
+    // Seen once, then missed, then confirmed by two consecutive frames.
+    assert!(session.observe(vec![barcode("a")], latency, at(0)).is_empty());
+    assert!(session.observe(vec![], latency, at(100)).is_empty());
+    assert!(session.observe(vec![barcode("a")], latency, at(200)).is_empty());
+    let rs = session.observe(vec![barcode("a"), barcode("a"), barcode("b")], latency, at(300));
+    assert_eq!(rs, vec![barcode("a")]);
+
+    // Held in view or brought back within the cooldown, only the new code is reported.
+    assert_eq!(session.observe(vec![barcode("a"), barcode("b")], latency, at(400)), vec![barcode("b")]);
+    assert!(session.observe(vec![], latency, at(500)).is_empty());
+    assert!(session.observe(vec![barcode("a")], latency, at(600)).is_empty());
+    assert!(session.observe(vec![barcode("a")], latency, at(700)).is_empty());
+    assert_eq!(session.observe(vec![barcode("a")], latency, at(1300)), vec![barcode("a")]);
+
+    session.drop_frame();
+    let stats = session.stats();
+    assert_eq!((stats.frames, stats.dropped, stats.reported), (9, 1, 3));
+    assert!((stats.fps - 8.0 / 1.3).abs() < 1e-9);
+    assert!((stats.latency_ms - 10.0).abs() < 1e-9);
+}
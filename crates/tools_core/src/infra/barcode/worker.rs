@@ -0,0 +1,142 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    thread,
+};
+
+use log::{debug, warn};
+use tokio::sync::oneshot;
+
+use super::{convert::Frame, detect_all, options::ScanOptions, BarcodeError, DecodedBarcode};
+
+type DecodeResult = Result<Option<Vec<DecodedBarcode>>, BarcodeError>;
+
+struct Job {
+    frame: Frame,
+    options: ScanOptions,
+    reply: oneshot::Sender<DecodeResult>,
+}
+
+#[derive(Default)]
+struct Slot {
+    job: Option<Job>,
+    closed: bool,
+}
+
+struct Shared {
+    slot: Mutex<Slot>,
+    ready: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Slot> {
+        self.slot.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Decodes camera frames on a dedicated thread, keeping at most one frame waiting.
+///
+/// A frame submitted while another is still waiting replaces it, and the replaced frame
+/// resolves to `None`. A decoder slower than the camera then skips frames instead of
+/// falling further and further behind.
+pub struct ScanWorker {
+    shared: Arc<Shared>,
+}
+
+impl Default for ScanWorker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScanWorker {
+    /// Start the worker thread, which stops when the worker is dropped.
+    pub fn new() -> Self {
+        let shared = Arc::new(Shared { slot: Mutex::new(Slot::default()), ready: Condvar::new() });
+        let worker_shared = shared.clone();
+        thread::Builder::new()
+            .name("barcode-scan".to_string())
+            .spawn(move || run(&worker_shared))
+            .expect("failed to spawn barcode scan thread");
+        Self { shared }
+    }
+
+    fn submit(&self, frame: Frame, options: ScanOptions) -> oneshot::Receiver<DecodeResult> {
+        let (reply, rx) = oneshot::channel();
+        if let Some(stale) = self.shared.lock().job.replace(Job { frame, options, reply }) {
+            debug!("Dropping stale {}x{} frame", stale.frame.width, stale.frame.height);
+            let _ = stale.reply.send(Ok(None));
+        }
+        self.shared.ready.notify_one();
+        rx
+    }
+
+    /// Decode all barcodes in a frame.
+    ///
+    /// # Arguments
+    /// * `frame` - The camera frame.
+    /// * `options` - The formats, hints and region to scan.
+    ///
+    /// # Returns
+    /// * `Ok(Some(Vec<DecodedBarcode>))` with every barcode found, empty if none is found.
+    /// * `Ok(None)` if the frame was dropped for a newer one before it was decoded.
+    /// * `Err(BarcodeError)` if the frame is invalid or an error occurs during decoding.
+    pub async fn decode(&self, frame: Frame, options: ScanOptions) -> DecodeResult {
+        // The worker only goes away with `self`, drop the frame if it does.
+        self.submit(frame, options).await.unwrap_or(Ok(None))
+    }
+}
+
+impl Drop for ScanWorker {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.ready.notify_one();
+    }
+}
+
+fn run(shared: &Shared) {
+    loop {
+        let job = {
+            let mut slot = shared.ready
+                .wait_while(shared.lock(), |x| x.job.is_none() && !x.closed)
+                .unwrap_or_else(PoisonError::into_inner);
+            if slot.closed {
+                return;
+            }
+            slot.job.take().expect("woken with a job")
+        };
+        if job.reply.is_closed() {
+            continue;
+        }
+
+        let Job { frame, options, reply } = job;
+        let rs = panic::catch_unwind(AssertUnwindSafe(|| detect_all(frame.into_luma()?, &options)))
+            .unwrap_or_else(|_| {
+                warn!("barcode decoder panicked");
+                Err(BarcodeError::DecoderPanicked)
+            })
+            .map(Some);
+        let _ = reply.send(rs);
+    }
+}
+
+#[test]
+fn test_scan_worker_drops_stale_frames() {
+    use super::{convert::PixelFormat, encode::{encode, EncodeOptions, Symbology}};
+
+    let bitmap = encode("FUMO-0042", Symbology::QrCode, &EncodeOptions::default()).unwrap().to_bitmap();
+    let frame = Frame { format: PixelFormat::Luma, data: bitmap.pixels, width: bitmap.width, height: bitmap.height };
+
+    // Submit frames before the worker thread starts taking them.
+    let shared = Arc::new(Shared { slot: Mutex::new(Slot::default()), ready: Condvar::new() });
+    let worker = ScanWorker { shared: shared.clone() };
+    let mut first = worker.submit(frame.clone(), ScanOptions::default());
+    let second = worker.submit(Frame { width: 1, ..frame.clone() }, ScanOptions::default());
+    assert!(matches!(first.try_recv(), Ok(Ok(None))));
+
+    thread::spawn(move || run(&shared));
+    assert!(second.blocking_recv().unwrap().is_err());
+    let rs = worker.submit(frame, ScanOptions::default()).blocking_recv().unwrap().unwrap().unwrap();
+    assert_eq!(rs.len(), 1);
+    assert_eq!(rs[0].text, "FUMO-0042");
+}
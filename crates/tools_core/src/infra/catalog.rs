@@ -0,0 +1,336 @@
+use std::{collections::HashSet, fs, path::PathBuf, str::FromStr};
+
+use log::{info, warn};
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::sku::{normalize_product_code, SkuError};
+
+/// The lowest fuzzy search score returned.
+const MIN_SCORE: f32 = 0.3;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CatalogError {
+    #[error(transparent)]
+    RusqliteError(#[from] rusqlite::Error),
+    #[error(transparent)]
+    TaskJoinError(#[from] tokio::task::JoinError),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    CsvError(#[from] csv::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    SkuError(#[from] SkuError),
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+}
+
+/// Product information for a JAN code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub jan: String,
+    pub name: String,
+    #[serde(default)]
+    pub character: Option<String>,
+    #[serde(default)]
+    pub series: Option<String>,
+    /// The release date as written in the source, e.g. `2025-08-09`.
+    #[serde(default)]
+    pub release: Option<String>,
+}
+
+impl CatalogEntry {
+    /// Normalize the JAN and drop empty optional fields.
+    fn normalize(mut self) -> Result<Self, CatalogError> {
+        self.jan = normalize_product_code(&self.jan)?;
+        self.name = self.name.trim().to_string();
+        if self.name.is_empty() {
+            return Err(CatalogError::InvalidArgument(format!("`{}` has no name", self.jan)));
+        }
+        for field in [&mut self.character, &mut self.series, &mut self.release] {
+            *field = field.take().map(|x| x.trim().to_string()).filter(|x| !x.is_empty());
+        }
+        Ok(self)
+    }
+}
+
+/// The file format of a catalog import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CatalogFormat {
+    /// A header row with `jan`, `name` and optionally `character`, `series` and `release` columns.
+    Csv,
+    /// An array of objects with the same keys as the CSV columns.
+    Json,
+}
+
+impl FromStr for CatalogFormat {
+    type Err = CatalogError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(CatalogFormat::Csv),
+            "json" => Ok(CatalogFormat::Json),
+            _ => {
+                warn!("Unknown catalog format: {}", s);
+                Err(CatalogError::InvalidArgument(format!("Unknown catalog format: {}", s)))
+            }
+        }
+    }
+}
+
+/// The result of a catalog import.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportReport {
+    /// The number of entries added or updated.
+    pub imported: usize,
+    /// Why each skipped row was skipped, e.g. `line 3: ...` or `entry 2: ...`.
+    pub skipped: Vec<String>,
+}
+
+/// A fuzzy search result.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogMatch {
+    pub entry: CatalogEntry,
+    /// From 0 to 1, 1 for a substring match.
+    pub score: f32,
+}
+
+/// Parse catalog entries, normalizing JANs and skipping invalid rows.
+fn parse(data: &[u8], format: CatalogFormat) -> Result<(Vec<CatalogEntry>, Vec<String>), CatalogError> {
+    let rows: Vec<(String, Result<CatalogEntry, CatalogError>)> = match format {
+        CatalogFormat::Csv => csv::Reader::from_reader(data)
+            .deserialize::<CatalogEntry>()
+            .enumerate()
+            // The header is line 1.
+            .map(|(i, x)| (format!("line {}", i + 2), x.map_err(CatalogError::from)))
+            .collect(),
+        CatalogFormat::Json => serde_json::from_slice::<Vec<serde_json::Value>>(data)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, x)| (format!("entry {}", i + 1), serde_json::from_value(x).map_err(CatalogError::from)))
+            .collect(),
+    };
+
+    let mut entries = vec![];
+    let mut skipped = vec![];
+    for (position, row) in rows {
+        match row.and_then(CatalogEntry::normalize) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => skipped.push(format!("{}: {}", position, e)),
+        }
+    }
+    Ok((entries, skipped))
+}
+
+/// Fold case, full-width ASCII and katakana, so `ＫＡＳＵＭＩ` finds `Kasumi` and `らばー` finds `ラバー`.
+fn fold(s: &str) -> Vec<char> {
+    s.chars()
+        .filter(|x| !x.is_whitespace())
+        .map(|x| match x {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(x as u32 - 0xFEE0).unwrap_or(x),
+            '\u{30A1}'..='\u{30F6}' => char::from_u32(x as u32 - 0x60).unwrap_or(x),
+            _ => x,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Score how well a folded query matches a text, from 0 to 1.
+///
+/// A substring scores 1, anything else the Dice coefficient of their character bigrams,
+/// which tolerates typos and word order for both Latin and Japanese text.
+fn score(query: &[char], text: &str) -> f32 {
+    let text = fold(text);
+    if query.is_empty() || text.is_empty() {
+        return 0.0;
+    }
+    if text.windows(query.len()).any(|x| x == query) {
+        return 1.0;
+    }
+    let bigrams = |x: &[char]| x.windows(2).map(|x| (x[0], x[1])).collect::<HashSet<_>>();
+    let (a, b) = (bigrams(query), bigrams(&text));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    2.0 * a.intersection(&b).count() as f32 / (a.len() + b.len()) as f32
+}
+
+fn score_entry(query: &[char], entry: &CatalogEntry) -> f32 {
+    [Some(&entry.jan), Some(&entry.name), entry.character.as_ref(), entry.series.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|x| score(query, x))
+        .fold(0.0, f32::max)
+}
+
+fn entry_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<CatalogEntry> {
+    Ok(CatalogEntry {
+        jan: row.get(0)?,
+        name: row.get(1)?,
+        character: row.get(2)?,
+        series: row.get(3)?,
+        release: row.get(4)?,
+    })
+}
+
+/// A local product catalog keyed by JAN code, stored in SQLite.
+pub struct Catalog {
+    db_path: String,
+}
+
+impl Catalog {
+    /// Open the catalog, creating the database and table if needed.
+    pub async fn open(db_path: &str) -> Result<Self, CatalogError> {
+        let path = PathBuf::from(db_path);
+        tokio::task::spawn_blocking(move || {
+            if let Some(parent_dir) = path.parent() {
+                fs::create_dir_all(parent_dir)?
+            }
+            let conn = Connection::open(path)?;
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS catalog (jan TEXT PRIMARY KEY, name TEXT NOT NULL, character TEXT, series TEXT, release TEXT)",
+                [],
+            )?;
+            Ok::<_, CatalogError>(())
+        }).await??;
+        Ok(Self { db_path: db_path.to_string() })
+    }
+
+    /// Add or update entries from a catalog file. Rows with an invalid JAN or no name are skipped.
+    ///
+    /// # Arguments
+    /// * `data` - The contents of the file.
+    /// * `format` - The file format.
+    ///
+    /// # Errors
+    /// * `CatalogError::CsvError` or `CatalogError::JsonError` if the file cannot be parsed at all.
+    pub async fn import(&self, data: Vec<u8>, format: CatalogFormat) -> Result<ImportReport, CatalogError> {
+        let db_path = self.db_path.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let (entries, skipped) = parse(&data, format)?;
+            let mut conn = Connection::open(db_path)?;
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT INTO catalog (jan, name, character, series, release) VALUES (?1, ?2, ?3, ?4, ?5) \
+                     ON CONFLICT(jan) DO UPDATE SET name = excluded.name, character = excluded.character, \
+                     series = excluded.series, release = excluded.release",
+                )?;
+                for x in &entries {
+                    stmt.execute((&x.jan, &x.name, &x.character, &x.series, &x.release))?;
+                }
+            }
+            tx.commit()?;
+            info!("Imported {} catalog entries, skipped {}", entries.len(), skipped.len());
+            Ok(ImportReport { imported: entries.len(), skipped })
+        }).await?
+    }
+
+    /// Add or update entries from a `.csv` or `.json` file on disk.
+    ///
+    /// # Errors
+    /// * `CatalogError::InvalidArgument` if the file extension is not `csv` or `json`.
+    /// * `CatalogError::IoError` if the file cannot be read.
+    pub async fn import_file(&self, path: PathBuf) -> Result<ImportReport, CatalogError> {
+        let format = path.extension()
+            .and_then(|x| x.to_str())
+            .unwrap_or_default()
+            .parse::<CatalogFormat>()?;
+        let data = tokio::task::spawn_blocking(move || {
+            fs::read(&path).inspect_err(|e| warn!("Failed to read catalog file {}: {}", path.display(), e))
+        }).await??;
+        self.import(data, format).await
+    }
+
+    /// Find the entry for a JAN code, normalized first. `None` if there is none or `jan`
+    /// is not a product code, e.g. an internal SKU.
+    pub async fn lookup(&self, jan: &str) -> Result<Option<CatalogEntry>, CatalogError> {
+        let db_path = self.db_path.clone();
+        let jan = match normalize_product_code(jan) {
+            Err(SkuError::NotAProductCode(_)) => return Ok(None),
+            rs => rs?,
+        };
+
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db_path)?;
+            let rs = conn.query_row(
+                "SELECT jan, name, character, series, release FROM catalog WHERE jan = ?1",
+                [jan],
+                entry_from_row,
+            ).optional()?;
+            Ok(rs)
+        }).await?
+    }
+
+    /// Fuzzy search by JAN, name, character or series.
+    ///
+    /// # Returns
+    /// * `Ok(Vec<CatalogMatch>)` with at most `limit` matches, best first.
+    pub async fn search(&self, query: &str, limit: usize) -> Result<Vec<CatalogMatch>, CatalogError> {
+        let db_path = self.db_path.clone();
+        let query = fold(query);
+
+        tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(db_path)?;
+            let mut stmt = conn.prepare("SELECT jan, name, character, series, release FROM catalog")?;
+            let mut rs = vec![];
+            for entry in stmt.query_map([], entry_from_row)? {
+                let entry = entry?;
+                let score = score_entry(&query, &entry);
+                if score >= MIN_SCORE {
+                    rs.push(CatalogMatch { entry, score });
+                }
+            }
+            rs.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.entry.name.cmp(&b.entry.name)));
+            rs.truncate(limit);
+            Ok(rs)
+        }).await?
+    }
+}
+
+#[test]
+fn test_catalog_parse() {
+    let csv = "jan,name,character,series,release\n\
+        4 901234 567894,ラバーストラップ 戸山香澄,戸山香澄,,2025-08-09\n\
+        4901234567890,Broken check digit,,,\n\
+        036000291452,  ,,,\n\
+        FUMO-0042,Internal code,,,\n";
+    let (entries, skipped) = parse(csv.as_bytes(), CatalogFormat::Csv).unwrap();
+    assert_eq!(entries, vec![CatalogEntry {
+        jan: "4901234567894".to_string(),
+        name: "ラバーストラップ 戸山香澄".to_string(),
+        character: Some("戸山香澄".to_string()),
+        series: None,
+        release: Some("2025-08-09".to_string()),
+    }]);
+    assert_eq!(skipped.len(), 3);
+    assert!(skipped[0].starts_with("line 3: "));
+    assert!(skipped[1].starts_with("line 4: "));
+    assert!(skipped[2].starts_with("line 5: "));
+
+    let json = r#"[{"jan": "45496608", "name": "Acrylic Stand"}, {"name": "No JAN"}]"#;
+    let (entries, skipped) = parse(json.as_bytes(), CatalogFormat::Json).unwrap();
+    assert_eq!(entries.len(), 1);
+    assert!(skipped[0].starts_with("entry 2: "));
+    assert!(parse(b"{}", CatalogFormat::Json).is_err());
+}
+
+#[test]
+fn test_catalog_score() {
+    let entry = CatalogEntry {
+        jan: "4901234567894".to_string(),
+        name: "ラバーストラップ 戸山香澄".to_string(),
+        character: Some("Kasumi Toyama".to_string()),
+        series: Some("Poppin'Party".to_string()),
+        release: None,
+    };
+    assert_eq!(score_entry(&fold("らばー"), &entry), 1.0);
+    assert_eq!(score_entry(&fold("ＫＡＳＵＭＩ"), &entry), 1.0);
+    assert_eq!(score_entry(&fold("49012345"), &entry), 1.0);
+    assert!(score_entry(&fold("kasumi toyoma"), &entry) >= MIN_SCORE);
+    assert!(score_entry(&fold("Roselia"), &entry) < MIN_SCORE);
+}
@@ -0,0 +1,541 @@
+use std::{error::Error, fmt::Display, path::Path, sync::Arc};
+
+use krilla::{
+    color::luma,
+    geom::{PathBuilder, Point, Rect},
+    page::PageSettings,
+    paint::Stroke,
+    surface::Surface,
+    text::{Font, TextDirection},
+    Document,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    domain::repository::{Repository, Storable},
+    infra::barcode::{
+        encode::{encode, EncodeOptions, ErrorCorrection, Symbology},
+        BarcodeError,
+    },
+};
+
+const PT_PER_MM: f32 = 72.0 / 25.4;
+const LINE_HEIGHT: f32 = 1.25;
+const ELLIPSIS: char = '…';
+
+/// Fonts with CJK coverage that ship with Windows, macOS and common Linux distributions.
+const SYSTEM_CJK_FONTS: &[&str] = &[
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\YuGothR.ttc",
+    "C:\\Windows\\Fonts\\msgothic.ttc",
+    "/System/Library/Fonts/Hiragino Sans GB.ttc",
+    "/System/Library/Fonts/ヒラギノ角ゴシック W3.ttc",
+    "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum LabelError {
+    #[error(transparent)]
+    BarcodeError(#[from] BarcodeError),
+    #[error(transparent)]
+    PdfError(#[from] krilla::error::KrillaError),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    TaskJoinError(#[from] tokio::task::JoinError),
+    #[error("repository error: {0}")]
+    RepositoryError(Box<dyn Error + Send + Sync>),
+    #[error("item `{0}` not found")]
+    ItemNotFound(Uuid),
+    #[error("no CJK font found on this system")]
+    FontNotFound,
+    #[error("invalid font: {0}")]
+    InvalidFont(String),
+    #[error("invalid layout: {0}")]
+    InvalidLayout(String),
+}
+
+/// A piece of item data printed on a label.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelField {
+    Id,
+    Sku,
+    /// The whole metadata.
+    Metadata,
+    /// A top-level key of metadata holding a JSON object, empty if the metadata is not one.
+    MetadataKey(String),
+}
+
+impl LabelField {
+    fn text<T>(&self, item: &T) -> String
+    where
+        T: Storable,
+        T::Sku: Display,
+        T::Metadata: Display,
+    {
+        match self {
+            LabelField::Id => item.id().to_string(),
+            LabelField::Sku => item.sku().to_string(),
+            LabelField::Metadata => item.metadata().to_string(),
+            LabelField::MetadataKey(key) => {
+                match serde_json::from_str::<serde_json::Value>(&item.metadata().to_string()) {
+                    Ok(serde_json::Value::Object(map)) => match map.get(key) {
+                        Some(serde_json::Value::String(x)) => x.clone(),
+                        Some(serde_json::Value::Null) | None => String::new(),
+                        Some(x) => x.to_string(),
+                    },
+                    _ => String::new(),
+                }
+            }
+        }
+    }
+}
+
+/// A sheet of labels in a grid. Lengths are in millimetres and the font size in points.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LabelLayout {
+    pub page_width: f32,
+    pub page_height: f32,
+    pub columns: u32,
+    pub rows: u32,
+    pub label_width: f32,
+    pub label_height: f32,
+    /// The distance from the top edge of the page to the first row.
+    pub margin_top: f32,
+    /// The distance from the left edge of the page to the first column.
+    pub margin_left: f32,
+    /// The horizontal gap between columns.
+    pub gap_x: f32,
+    /// The vertical gap between rows.
+    pub gap_y: f32,
+    /// The space kept clear inside each label.
+    pub padding: f32,
+    /// Encoded in a QR code on the left of the label, or no QR code if `None`.
+    pub code: Option<LabelField>,
+    pub error_correction: ErrorCorrection,
+    /// Printed right of the QR code in order, each wrapped over as many lines as fit.
+    pub fields: Vec<LabelField>,
+    pub font_size: f32,
+    /// Draw the outline of each label, for cutting labels out of plain paper.
+    pub border: bool,
+}
+
+impl LabelLayout {
+    fn a4(columns: u32, rows: u32, label_width: f32, label_height: f32, margin_top: f32, margin_left: f32, gap_x: f32) -> Self {
+        Self {
+            page_width: 210.0,
+            page_height: 297.0,
+            columns,
+            rows,
+            label_width,
+            label_height,
+            margin_top,
+            margin_left,
+            gap_x,
+            gap_y: 0.0,
+            padding: 1.5,
+            code: Some(LabelField::Id),
+            error_correction: ErrorCorrection::M,
+            fields: vec![LabelField::Sku, LabelField::Metadata],
+            font_size: 8.0,
+            border: false,
+        }
+    }
+
+    /// A4, 3 × 7 labels of 63.5 × 38.1 mm, Avery L7160 and compatibles.
+    pub fn a4_21() -> Self {
+        Self::a4(3, 7, 63.5, 38.1, 15.15, 7.25, 2.5)
+    }
+
+    /// A4, 3 × 8 labels of 70 × 37 mm without margins.
+    pub fn a4_24() -> Self {
+        Self::a4(3, 8, 70.0, 37.0, 0.5, 0.0, 0.0)
+    }
+
+    /// A4, 5 × 13 labels of 38.1 × 21.2 mm, Avery L7651 and compatibles.
+    pub fn a4_65() -> Self {
+        Self { font_size: 5.0, fields: vec![LabelField::Sku], ..Self::a4(5, 13, 38.1, 21.2, 10.7, 4.75, 2.5) }
+    }
+
+    /// The built-in layouts and their names.
+    pub fn presets() -> Vec<(&'static str, Self)> {
+        vec![
+            ("A4 21 (63.5 × 38.1 mm)", Self::a4_21()),
+            ("A4 24 (70 × 37 mm)", Self::a4_24()),
+            ("A4 65 (38.1 × 21.2 mm)", Self::a4_65()),
+        ]
+    }
+
+    /// The number of labels on a page.
+    pub fn labels_per_page(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+
+    /// The top-left corner of the label at `index` on its page, in points.
+    fn label_origin(&self, index: usize) -> (f32, f32) {
+        let column = (index % self.columns as usize) as f32;
+        let row = (index / self.columns as usize) as f32;
+        (
+            (self.margin_left + column * (self.label_width + self.gap_x)) * PT_PER_MM,
+            (self.margin_top + row * (self.label_height + self.gap_y)) * PT_PER_MM,
+        )
+    }
+
+    /// Check that the grid is not empty and fits on the page.
+    ///
+    /// # Errors
+    /// * `LabelError::InvalidLayout` describing the first problem found.
+    pub fn validate(&self) -> Result<(), LabelError> {
+        let invalid = |msg: String| {
+            warn!("Invalid label layout: {}", msg);
+            Err(LabelError::InvalidLayout(msg))
+        };
+
+        let lengths = [
+            self.page_width, self.page_height, self.label_width, self.label_height,
+            self.margin_top, self.margin_left, self.gap_x, self.gap_y, self.padding,
+        ];
+        if lengths.iter().any(|x| !x.is_finite() || *x < 0.0) || !self.font_size.is_finite() || self.font_size <= 0.0 {
+            return invalid("Lengths must be non-negative and the font size positive".to_string());
+        }
+        if self.columns == 0 || self.rows == 0 || self.label_width <= 0.0 || self.label_height <= 0.0 {
+            return invalid(format!(
+                "Empty grid: {} × {} labels of {} × {} mm",
+                self.columns, self.rows, self.label_width, self.label_height
+            ));
+        }
+        if self.padding * 2.0 >= self.label_width.min(self.label_height) {
+            return invalid(format!("Padding {} mm leaves no room on the label", self.padding));
+        }
+        // Allow for rounding in the published dimensions of sticker sheets.
+        const TOLERANCE: f32 = 0.05;
+        let columns = self.columns as f32;
+        let rows = self.rows as f32;
+        let width = self.margin_left + columns * self.label_width + (columns - 1.0) * self.gap_x;
+        let height = self.margin_top + rows * self.label_height + (rows - 1.0) * self.gap_y;
+        if width > self.page_width + TOLERANCE || height > self.page_height + TOLERANCE {
+            return invalid(format!(
+                "Grid of {} × {} mm does not fit on the page of {} × {} mm",
+                width, height, self.page_width, self.page_height
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The contents of one label.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    /// The QR code contents, no QR code is drawn if `None` or empty.
+    pub code: Option<String>,
+    pub fields: Vec<String>,
+}
+
+impl Label {
+    /// Take the fields of `layout` from an item. The label has no QR code if its field is empty for the item.
+    pub fn from_item<T>(item: &T, layout: &LabelLayout) -> Self
+    where
+        T: Storable,
+        T::Sku: Display,
+        T::Metadata: Display,
+    {
+        Self {
+            code: layout.code.as_ref().map(|x| x.text(item)).filter(|x| !x.is_empty()),
+            fields: layout.fields.iter().map(|x| x.text(item)).collect(),
+        }
+    }
+}
+
+/// A TrueType or OpenType font, or a font in a collection, to print label text with.
+///
+/// Only the glyphs used are embedded in the PDF, so large CJK fonts are fine.
+#[derive(Debug, Clone)]
+pub struct LabelFont {
+    data: Arc<Vec<u8>>,
+    index: u32,
+    font: Font,
+}
+
+impl LabelFont {
+    /// # Errors
+    /// * `LabelError::InvalidFont` if the data is not a font or has no font at `index`.
+    pub fn new(data: Vec<u8>, index: u32) -> Result<Self, LabelError> {
+        let data = Arc::new(data);
+        if let Err(e) = ttf_parser::Face::parse(&data, index) {
+            warn!("Invalid font: {}", e);
+            return Err(LabelError::InvalidFont(e.to_string()));
+        }
+        let font = Font::new(data.clone().into(), index)
+            .ok_or_else(|| LabelError::InvalidFont("Unsupported font".to_string()))?;
+        Ok(Self { data, index, font })
+    }
+
+    /// Load the first font of a font file or collection.
+    ///
+    /// # Errors
+    /// * `LabelError::IoError` if the file cannot be read.
+    /// * `LabelError::InvalidFont` if the file is not a font.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, LabelError> {
+        Self::new(std::fs::read(path)?, 0)
+    }
+
+    /// Load a CJK font installed with the operating system.
+    ///
+    /// # Errors
+    /// * `LabelError::FontNotFound` if none of the known fonts is installed.
+    pub fn system_cjk() -> Result<Self, LabelError> {
+        let font = SYSTEM_CJK_FONTS.iter()
+            .filter(|x| Path::new(x).is_file())
+            .find_map(|x| Self::open(x).inspect(|_| info!("Using label font {}", x)).ok());
+        font.ok_or(LabelError::FontNotFound)
+    }
+
+    fn face(&self) -> ttf_parser::Face<'_> {
+        ttf_parser::Face::parse(&self.data, self.index).expect("font was parsed when loaded")
+    }
+}
+
+/// Whether a line may break before or after `c` without a space, as between CJK characters.
+fn is_wide(c: char) -> bool {
+    ('\u{2E80}'..='\u{D7FF}').contains(&c) || ('\u{F900}'..='\u{FAFF}').contains(&c) || ('\u{FF00}'..='\u{FFEF}').contains(&c)
+}
+
+/// Wrap `fields` to lines of at most `max_width`.
+///
+/// Lines break at spaces, between CJK characters, or inside words too long for a line.
+/// Empty fields are skipped. If the lines do not fit in `max_lines`, the last line kept ends
+/// with an ellipsis.
+fn fit_lines(fields: &[String], max_width: f32, max_lines: usize, width: impl Fn(char) -> f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    for field in fields.iter().filter(|x| !x.trim().is_empty()) {
+        let mut line = String::new();
+        for c in field.chars().map(|c| if c.is_control() { ' ' } else { c }) {
+            if !line.is_empty() && line.chars().chain([c]).map(&width).sum::<f32>() > max_width {
+                if c == ' ' {
+                    lines.push(std::mem::take(&mut line));
+                    continue;
+                }
+                // Carry the word being written over to the next line.
+                let word_start = line.rfind(' ').map(|x| x + 1)
+                    .filter(|&x| !is_wide(c) && !line[x..].chars().any(is_wide))
+                    .unwrap_or(line.len());
+                let word = line.split_off(word_start);
+                lines.push(line.trim_end().to_string());
+                line = word;
+            }
+            line.push(c);
+        }
+        lines.push(line);
+    }
+
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            let ellipsis_width = width(ELLIPSIS);
+            while !last.is_empty() && last.chars().map(&width).sum::<f32>() + ellipsis_width > max_width {
+                last.pop();
+            }
+            last.push(ELLIPSIS);
+        }
+    }
+    lines
+}
+
+fn draw_label(surface: &mut Surface, label: &Label, origin: (f32, f32), layout: &LabelLayout, font: &LabelFont) -> Result<(), LabelError> {
+    let (x, y) = origin;
+    let width = layout.label_width * PT_PER_MM;
+    let height = layout.label_height * PT_PER_MM;
+    let padding = layout.padding * PT_PER_MM;
+
+    if layout.border {
+        let mut path = PathBuilder::new();
+        path.push_rect(Rect::from_xywh(x, y, width, height).expect("label size was validated"));
+        surface.set_stroke(Some(Stroke { paint: luma::Color::new(192).into(), width: 0.25, ..Default::default() }));
+        surface.draw_path(&path.finish().expect("label path is not empty"));
+        surface.set_stroke(None);
+    }
+
+    let mut text_left = x + padding;
+    // An empty QR code cannot be encoded, leave it out rather than failing the whole sheet.
+    if let Some(code) = label.code.as_deref().filter(|x| !x.is_empty()) {
+        // The QR code keeps its own quiet zone, so it can be read next to the text.
+        let options = EncodeOptions { error_correction: layout.error_correction, module_size: 1, ..Default::default() };
+        let symbol = encode(code, Symbology::QrCode, &options)?;
+        let side = height - padding * 2.0;
+        let module = side / symbol.columns() as f32;
+        let mut path = PathBuilder::new();
+        for row in 0..symbol.rows() {
+            for column in (0..symbol.columns()).filter(|&c| symbol.is_dark(c, row)) {
+                let rect = Rect::from_xywh(x + padding + column as f32 * module, y + padding + row as f32 * module, module, module);
+                path.push_rect(rect.expect("module size is positive"));
+            }
+        }
+        if let Some(path) = path.finish() {
+            surface.draw_path(&path);
+        }
+        text_left += side;
+    }
+
+    let text_width = x + width - padding - text_left;
+    let text_height = height - padding * 2.0;
+    let line_height = layout.font_size * LINE_HEIGHT;
+    let max_lines = (text_height / line_height) as usize;
+    if text_width < layout.font_size || max_lines == 0 {
+        return Ok(());
+    }
+
+    let face = font.face();
+    let scale = layout.font_size / face.units_per_em() as f32;
+    let glyph_width = |c: char| {
+        let glyph = face.glyph_index(c).unwrap_or(ttf_parser::GlyphId(0));
+        face.glyph_hor_advance(glyph).unwrap_or(0) as f32 * scale
+    };
+    let lines = fit_lines(&label.fields, text_width, max_lines, glyph_width);
+
+    // Center the text block vertically, the first baseline is one ascent below its top.
+    let block_height = lines.len() as f32 * line_height;
+    let mut baseline = y + padding + (text_height - block_height) / 2.0
+        + (line_height - layout.font_size) / 2.0
+        + face.ascender() as f32 * scale;
+    for line in lines {
+        surface.draw_text(Point::from_xy(text_left, baseline), font.font.clone(), layout.font_size, &line, false, TextDirection::Auto);
+        baseline += line_height;
+    }
+    Ok(())
+}
+
+/// Render labels to a PDF, filling the grid row by row and adding pages as needed.
+///
+/// # Arguments
+/// * `labels` - The labels in print order.
+/// * `layout` - The sheet layout.
+/// * `font` - The font for label text, it should cover every script in the labels.
+/// * `skip` - The number of positions to leave blank at the start of the first page,
+///   to print on a partly used sheet.
+///
+/// # Errors
+/// * `LabelError::InvalidLayout` if the layout is invalid.
+/// * `LabelError::BarcodeError` if a QR code cannot hold its contents.
+/// * `LabelError::PdfError` if the PDF cannot be written.
+pub fn render_pdf(labels: &[Label], layout: &LabelLayout, font: &LabelFont, skip: usize) -> Result<Vec<u8>, LabelError> {
+    layout.validate()?;
+
+    let per_page = layout.labels_per_page();
+    let positions = skip + labels.len();
+    let pages = positions.div_ceil(per_page).max(1);
+    let page_settings = PageSettings::from_wh(layout.page_width * PT_PER_MM, layout.page_height * PT_PER_MM)
+        .expect("page size was validated");
+
+    let mut document = Document::new();
+    for page_index in 0..pages {
+        let mut page = document.start_page_with(page_settings.clone());
+        let mut surface = page.surface();
+        let first = page_index * per_page;
+        for position in first.max(skip)..(first + per_page).min(positions) {
+            draw_label(&mut surface, &labels[position - skip], layout.label_origin(position - first), layout, font)?;
+        }
+        surface.finish();
+        page.finish();
+    }
+    Ok(document.finish()?)
+}
+
+/// Render labels of items in a repository to a PDF.
+///
+/// # Arguments
+/// * `repo` - The repository to take items from.
+/// * `ids` - The items to print in order, or every item in the repository if `None`.
+/// * `layout` - The sheet layout, which also selects the fields printed.
+/// * `font` - The font for label text.
+/// * `skip` - The number of positions to leave blank at the start of the first page.
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` with the PDF file contents.
+/// * `Err(LabelError::ItemNotFound)` if an item in `ids` is not in the repository.
+/// * `Err(LabelError)` if the repository cannot be read or the PDF cannot be rendered.
+pub async fn render_repository<T, R>(
+    repo: &R,
+    ids: Option<&[Uuid]>,
+    layout: LabelLayout,
+    font: LabelFont,
+    skip: usize,
+) -> Result<Vec<u8>, LabelError>
+where
+    T: Storable + Send,
+    T::Sku: Display,
+    T::Metadata: Display,
+    R: Repository<T> + Sync,
+{
+    let items = repo.list().await.map_err(|e| LabelError::RepositoryError(Box::new(e)))?;
+    let labels = match ids {
+        Some(ids) => ids.iter()
+            .map(|id| {
+                items.iter()
+                    .find(|x| x.id() == *id)
+                    .map(|x| Label::from_item(x, &layout))
+                    .ok_or(LabelError::ItemNotFound(*id))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => items.iter().map(|x| Label::from_item(x, &layout)).collect(),
+    };
+
+    tokio::task::spawn_blocking(move || render_pdf(&labels, &layout, &font, skip))
+        .await
+        .inspect_err(|e| warn!("label render task join error: {e}"))?
+}
+
+#[test]
+fn test_label_layout_presets() {
+    for (name, layout) in LabelLayout::presets() {
+        assert!(layout.validate().is_ok(), "{}", name);
+    }
+    let layout = LabelLayout::a4_21();
+    let (x, y) = layout.label_origin(4);
+    assert!((x / PT_PER_MM - (7.25 + 66.0)).abs() < 1e-3);
+    assert!((y / PT_PER_MM - (15.15 + 38.1)).abs() < 1e-3);
+
+    assert!(LabelLayout { columns: 4, ..LabelLayout::a4_21() }.validate().is_err());
+    assert!(LabelLayout { padding: 20.0, ..LabelLayout::a4_21() }.validate().is_err());
+    assert!(LabelLayout { font_size: 0.0, ..LabelLayout::a4_21() }.validate().is_err());
+}
+
+#[test]
+fn test_label_from_item() {
+    use crate::domain::repository::Item;
+
+    let layout = LabelLayout {
+        fields: vec![
+            LabelField::Sku,
+            LabelField::MetadataKey("name".to_string()),
+            LabelField::MetadataKey("count".to_string()),
+            LabelField::MetadataKey("missing".to_string()),
+        ],
+        ..LabelLayout::a4_21()
+    };
+    let item = Item::new("4901234567894".to_string(), r#"{"name": "博麗霊夢 ふもふも", "count": 2}"#.to_string());
+    let label = Label::from_item(&item, &layout);
+    assert_eq!(label.code, Some(item.id().to_string()));
+    assert_eq!(label.fields, ["4901234567894", "博麗霊夢 ふもふも", "2", ""]);
+
+    let item = Item::new("sku".to_string(), "not json".to_string());
+    assert_eq!(Label::from_item(&item, &layout).fields, ["sku", "", "", ""]);
+
+    let layout = LabelLayout { code: Some(LabelField::MetadataKey("url".to_string())), ..layout };
+    assert_eq!(Label::from_item(&item, &layout).code, None);
+}
+
+#[test]
+fn test_fit_lines() {
+    let fields = ["ABCDEFG".to_string(), String::new(), "深圳 東京".to_string(), "ab cd efg 東京ab".to_string()];
+    let width = |c: char| if c.is_ascii() { 1.0 } else { 2.0 };
+    assert_eq!(fit_lines(&fields, 4.0, 9, width), ["ABCD", "EFG", "深圳", "東京", "ab", "cd", "efg", "東京", "ab"]);
+    assert_eq!(fit_lines(&fields, 4.0, 3, width), ["ABCD", "EFG", "深…"]);
+    assert_eq!(fit_lines(&fields[3..], 6.0, 9, width), ["ab cd", "efg 東", "京ab"]);
+}
@@ -1,3 +1,7 @@
+pub mod audit;
+pub mod backup;
 pub mod barcode;
+pub mod catalog;
+pub mod label;
 pub mod nfc;
 pub mod repository;
\ No newline at end of file
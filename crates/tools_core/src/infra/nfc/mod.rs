@@ -2,11 +2,20 @@ use log::info;
 use nfc1::{Context, Device};
 
 pub mod ntag213;
+pub mod trace;
+pub mod transport;
+pub mod uri;
 
 #[derive(Debug, thiserror::Error)]
 pub enum NfcError {
     #[error(transparent)]
     NfcError(#[from] nfc1::Error),
+    #[error("no tag in field")]
+    NoTag,
+    #[error("tag removed from field during operation")]
+    TagRemoved,
+    #[error("tag NAK: {0}")]
+    Nak(Nak),
     #[error("invalid target")]
     InvalidTarget,
     #[error("invalid argument: {0}")]
@@ -15,6 +24,59 @@ pub enum NfcError {
     UnexpectedResponse(String),
 }
 
+impl NfcError {
+    /// Whether the operation may succeed if tried again, possibly after the tag is presented again.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            NfcError::NfcError(e) => matches!(
+                e,
+                nfc1::Error::Timeout
+                    | nfc1::Error::RfTransmissionError
+                    | nfc1::Error::TargetReleased
+                    | nfc1::Error::OperationAborted
+            ),
+            NfcError::NoTag | NfcError::TagRemoved | NfcError::UnexpectedResponse(_) => true,
+            NfcError::Nak(nak) => nak.is_retryable(),
+            NfcError::InvalidTarget | NfcError::InvalidArgument(_) => false,
+        }
+    }
+}
+
+/// Negative acknowledge of a tag, the 4-bit reply to a failed command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum Nak {
+    #[error("invalid argument")]
+    InvalidArgument,
+    #[error("parity or CRC error")]
+    CrcError,
+    #[error("authentication counter overflow")]
+    AuthCounterOverflow,
+    #[error("EEPROM write error")]
+    EepromWriteError,
+    #[error("unknown NAK code {0:#X}")]
+    Unknown(u8),
+}
+
+impl Nak {
+    const ACK: u8 = 0x0A;
+
+    /// Decode a 4-bit reply, `None` if it is an ACK.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code & 0x0F {
+            Self::ACK => None,
+            0x00 => Some(Nak::InvalidArgument),
+            0x01 => Some(Nak::CrcError),
+            0x04 => Some(Nak::AuthCounterOverflow),
+            0x05 => Some(Nak::EepromWriteError),
+            x => Some(Nak::Unknown(x)),
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Nak::CrcError | Nak::EepromWriteError)
+    }
+}
+
 pub fn list_reader() -> Result<Vec<String>, NfcError> {
     const MAX_DEVICES: usize = 8;
     info!("finding NFC readers");
@@ -38,4 +100,16 @@ pub fn open_reader(name: Option<&str>, ctx: &mut Context) -> Result<Device, NfcE
         }
     };
     Ok(device)
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_nak_from_code() {
+    assert_eq!(Nak::from_code(0x0A), None);
+    assert_eq!(Nak::from_code(0x00), Some(Nak::InvalidArgument));
+    assert_eq!(Nak::from_code(0x01), Some(Nak::CrcError));
+    assert_eq!(Nak::from_code(0x04), Some(Nak::AuthCounterOverflow));
+    assert_eq!(Nak::from_code(0x05), Some(Nak::EepromWriteError));
+    assert_eq!(Nak::from_code(0x07), Some(Nak::Unknown(0x07)));
+    assert!(NfcError::Nak(Nak::CrcError).is_retryable());
+    assert!(!NfcError::Nak(Nak::AuthCounterOverflow).is_retryable());
+}
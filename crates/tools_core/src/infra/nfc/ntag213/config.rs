@@ -0,0 +1,215 @@
+use log::warn;
+
+use super::{NfcError, Variant, USER_PAGE_ADDR_MIN};
+
+/// The length of CFG0 and CFG1 in bytes.
+pub(super) const CONFIG_LEN: usize = 8;
+
+const AUTHLIM_MAX: u8 = 0b111;
+const MIRROR_BYTE_MAX: u8 = 0b11;
+
+/// What is mirrored into user memory as ASCII, `MIRROR_CONF` in CFG0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MirrorConf {
+    #[default]
+    None,
+    Uid,
+    NfcCounter,
+    UidAndNfcCounter,
+}
+
+impl MirrorConf {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => MirrorConf::None,
+            0b01 => MirrorConf::Uid,
+            0b10 => MirrorConf::NfcCounter,
+            _ => MirrorConf::UidAndNfcCounter,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            MirrorConf::None => 0b00,
+            MirrorConf::Uid => 0b01,
+            MirrorConf::NfcCounter => 0b10,
+            MirrorConf::UidAndNfcCounter => 0b11,
+        }
+    }
+
+    /// The number of ASCII bytes the mirror occupies in user memory.
+    fn mirror_len(self) -> usize {
+        const UID_MIRROR_LEN: usize = 14;
+        const NFC_COUNTER_MIRROR_LEN: usize = 6;
+
+        match self {
+            MirrorConf::None => 0,
+            MirrorConf::Uid => UID_MIRROR_LEN,
+            MirrorConf::NfcCounter => NFC_COUNTER_MIRROR_LEN,
+            // The UID and the counter are separated by an `x`.
+            MirrorConf::UidAndNfcCounter => UID_MIRROR_LEN + 1 + NFC_COUNTER_MIRROR_LEN,
+        }
+    }
+}
+
+/// Typed view of the configuration pages CFG0 and CFG1 of an NTAG21x card.
+///
+/// RFUI bits are not kept and are written back as zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TagConfig {
+    /// `MIRROR_CONF`, what is mirrored into user memory.
+    pub mirror_conf: MirrorConf,
+    /// `MIRROR_BYTE`, the byte offset within `mirror_page` where the mirror starts.
+    pub mirror_byte: u8,
+    /// `STRG_MOD_EN`, strong modulation mode.
+    pub strg_mod_en: bool,
+    /// `MIRROR_PAGE`, the page where the mirror starts.
+    pub mirror_page: u8,
+    /// `AUTH0`, the first page protected by the password.
+    pub auth0: u8,
+    /// `PROT`, whether read access is protected by the password as well as write access.
+    pub prot: bool,
+    /// `CFGLCK`, permanently locks the configuration pages once written.
+    pub cfglck: bool,
+    /// `NFC_CNT_EN`, the NFC counter.
+    pub nfc_cnt_en: bool,
+    /// `NFC_CNT_PWD_PROT`, whether reading the NFC counter requires the password.
+    pub nfc_cnt_pwd_prot: bool,
+    /// `AUTHLIM`, the limit of negative password attempts, 0 disables the limit.
+    pub authlim: u8,
+}
+
+impl Default for TagConfig {
+    /// The factory configuration.
+    fn default() -> Self {
+        Self {
+            mirror_conf: MirrorConf::None,
+            mirror_byte: 0,
+            strg_mod_en: true,
+            mirror_page: 0,
+            auth0: 0xFF,
+            prot: false,
+            cfglck: false,
+            nfc_cnt_en: false,
+            nfc_cnt_pwd_prot: false,
+            authlim: 0,
+        }
+    }
+}
+
+impl TagConfig {
+    /// Parse the configuration from the bytes of CFG0 followed by CFG1.
+    ///
+    /// # Errors
+    /// * `NfcError::UnexpectedResponse` if `data` is shorter than 8 bytes.
+    pub fn parse(data: &[u8]) -> Result<Self, NfcError> {
+        if data.len() < CONFIG_LEN {
+            warn!("Unexpected config length: expected {}, got {}", CONFIG_LEN, data.len());
+            return Err(NfcError::UnexpectedResponse(format!(
+                "Unexpected config length: expected {}, got {}",
+                CONFIG_LEN,
+                data.len()
+            )));
+        }
+        let mirror = data[0];
+        let access = data[4];
+        Ok(Self {
+            mirror_conf: MirrorConf::from_bits(mirror >> 6),
+            mirror_byte: (mirror >> 4) & MIRROR_BYTE_MAX,
+            strg_mod_en: mirror & (1 << 2) != 0,
+            mirror_page: data[2],
+            auth0: data[3],
+            prot: access & (1 << 7) != 0,
+            cfglck: access & (1 << 6) != 0,
+            nfc_cnt_en: access & (1 << 4) != 0,
+            nfc_cnt_pwd_prot: access & (1 << 3) != 0,
+            authlim: access & AUTHLIM_MAX,
+        })
+    }
+
+    /// Serialize the configuration to the bytes of CFG0 followed by CFG1.
+    pub fn to_bytes(&self) -> [u8; CONFIG_LEN] {
+        let mirror = (self.mirror_conf.bits() << 6)
+            | ((self.mirror_byte & MIRROR_BYTE_MAX) << 4)
+            | ((self.strg_mod_en as u8) << 2);
+        let access = ((self.prot as u8) << 7)
+            | ((self.cfglck as u8) << 6)
+            | ((self.nfc_cnt_en as u8) << 4)
+            | ((self.nfc_cnt_pwd_prot as u8) << 3)
+            | (self.authlim & AUTHLIM_MAX);
+        [mirror, 0x00, self.mirror_page, self.auth0, access, 0x00, 0x00, 0x00]
+    }
+
+    /// Check the configuration for illegal values and combinations on the given chip variant.
+    ///
+    /// # Errors
+    /// * `NfcError::InvalidArgument` describing the first problem found.
+    pub fn validate(&self, variant: Variant) -> Result<(), NfcError> {
+        let invalid = |msg: String| {
+            warn!("Invalid tag config: {}", msg);
+            Err(NfcError::InvalidArgument(msg))
+        };
+
+        if self.mirror_byte > MIRROR_BYTE_MAX {
+            return invalid(format!("Mirror byte out of bounds: {}", self.mirror_byte));
+        }
+        if self.authlim > AUTHLIM_MAX {
+            return invalid(format!("AUTHLIM out of bounds: {}", self.authlim));
+        }
+        if self.mirror_conf != MirrorConf::None {
+            let page = self.mirror_page as usize;
+            let start = page * 4 + self.mirror_byte as usize;
+            let end = start + self.mirror_conf.mirror_len() - 1;
+            let user_end = variant.user_page_addr_max() * 4 + 3;
+            if page < USER_PAGE_ADDR_MIN || end > user_end {
+                return invalid(format!(
+                    "Mirror out of user memory for {:?}: page {}, byte {}",
+                    variant, self.mirror_page, self.mirror_byte
+                ));
+            }
+        }
+        if matches!(self.mirror_conf, MirrorConf::NfcCounter | MirrorConf::UidAndNfcCounter) && !self.nfc_cnt_en {
+            return invalid("NFC counter mirror requires NFC_CNT_EN".to_string());
+        }
+        if self.nfc_cnt_pwd_prot && !self.nfc_cnt_en {
+            return invalid("NFC_CNT_PWD_PROT requires NFC_CNT_EN".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_tag_config_roundtrip() {
+    let data = [0x04, 0x00, 0x00, 0xFF, 0x00, 0x00, 0x00, 0x00];
+    let config = TagConfig::parse(&data).unwrap();
+    assert_eq!(config, TagConfig::default());
+    assert_eq!(config.to_bytes(), data);
+
+    let data = [0x54, 0x00, 0x0B, 0x10, 0xD5, 0x00, 0x00, 0x00];
+    let config = TagConfig::parse(&data).unwrap();
+    assert_eq!(config.mirror_conf, MirrorConf::Uid);
+    assert_eq!(config.mirror_byte, 1);
+    assert_eq!(config.mirror_page, 0x0B);
+    assert!(config.prot && config.cfglck && config.nfc_cnt_en && !config.nfc_cnt_pwd_prot);
+    assert_eq!(config.authlim, 5);
+    assert_eq!(config.to_bytes(), data);
+}
+
+#[test]
+fn test_tag_config_validate() {
+    let mut config = TagConfig {
+        mirror_conf: MirrorConf::Uid,
+        mirror_page: 0x24,
+        mirror_byte: 1,
+        ..Default::default()
+    };
+    assert!(config.validate(Variant::Ntag213).is_ok());
+    config.mirror_byte = 3;
+    assert!(config.validate(Variant::Ntag213).is_err());
+    assert!(config.validate(Variant::Ntag215).is_ok());
+
+    config.mirror_conf = MirrorConf::NfcCounter;
+    assert!(config.validate(Variant::Ntag215).is_err());
+    config.nfc_cnt_en = true;
+    assert!(config.validate(Variant::Ntag215).is_ok());
+}
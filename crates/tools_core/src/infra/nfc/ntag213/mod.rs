@@ -0,0 +1,724 @@
+use log::warn;
+use nfc1::{target_info::TargetInfo, Modulation};
+
+use super::{transport::Transport, uri, Nak, NfcError};
+
+mod config;
+
+pub use config::{MirrorConf, TagConfig};
+
+const NTAG213_MODULATION: Modulation = Modulation {
+    modulation_type: nfc1::ModulationType::Iso14443a,
+    baud_rate: nfc1::BaudRate::Baud106,
+};
+const UID_LEN: usize = 7;
+const RX_LEN: usize = 256;
+const WRITE_PAGE_ADDR_MIN: usize = 0x02;
+const READ_PAGE_ADDR_MAX: usize = 0x2C;
+
+const LOCK_PAGE_ADDR: usize = 0x02;
+const CC_PAGE_ADDR: usize = 0x03;
+const USER_PAGE_ADDR_MIN: usize = 0x04;
+
+mod cmd_code {
+    pub const GET_VERSION: u8 = 0x60;
+    pub const READ: u8 = 0x30;
+    pub const WRITE: u8 = 0xA2;
+}
+
+/// Chip variants of the NTAG21x family, told apart by the storage size byte of `GET_VERSION`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Ntag213,
+    Ntag215,
+    Ntag216,
+}
+
+impl Variant {
+    fn from_version(version: &[u8]) -> Option<Self> {
+        const VERSION_PREFIX: [u8; 6] = [0x00, 0x04, 0x04, 0x02, 0x01, 0x00];
+        const VERSION_PROTOCOL: u8 = 0x03;
+
+        if version.len() != 8 || version[..6] != VERSION_PREFIX || version[7] != VERSION_PROTOCOL {
+            return None;
+        }
+        match version[6] {
+            0x0F => Some(Variant::Ntag213),
+            0x11 => Some(Variant::Ntag215),
+            0x13 => Some(Variant::Ntag216),
+            _ => None,
+        }
+    }
+
+    /// The last page of user memory.
+    pub fn user_page_addr_max(self) -> usize {
+        match self {
+            Variant::Ntag213 => 0x27,
+            Variant::Ntag215 => 0x81,
+            Variant::Ntag216 => 0xE1,
+        }
+    }
+
+    /// The size of user memory in bytes.
+    pub fn user_len(self) -> usize {
+        (self.user_page_addr_max() - USER_PAGE_ADDR_MIN + 1) * 4
+    }
+
+    /// The page address of CFG0, followed by CFG1.
+    pub fn cfg0_page_addr(self) -> usize {
+        self.user_page_addr_max() + 2
+    }
+
+    /// The last page of the tag, PACK.
+    pub fn page_addr_max(self) -> usize {
+        self.cfg0_page_addr() + 3
+    }
+
+    /// The NFC Forum Type 2 Tag capability container for the user memory size.
+    pub fn capability_container(self) -> [u8; 4] {
+        const CC_MAGIC: u8 = 0xE1;
+        const CC_VERSION: u8 = 0x10;
+        const CC_ACCESS_READ_WRITE: u8 = 0x00;
+
+        let data_area_size = match self {
+            Variant::Ntag213 => 0x12,
+            Variant::Ntag215 => 0x3E,
+            Variant::Ntag216 => 0x6D,
+        };
+        [CC_MAGIC, CC_VERSION, data_area_size, CC_ACCESS_READ_WRITE]
+    }
+}
+
+/// Scan for an NTAG213 card and return its UID.
+///
+/// # Arguments
+/// * `reader` - The NFC reader device to scan with.
+///
+/// # Returns
+/// * `Ok(String)` containing the UID of the scanned NTAG213 card.
+///
+/// # Errors
+/// * `NfcError::NfcError` if there is an error during the NFC communication.
+/// * `NfcError::NoTag` if there is no tag in the field of the reader.
+/// * `NfcError::InvalidTarget` if the target is not a valid NTAG21x card or the UID length is not as expected.
+pub fn scan<T: Transport + ?Sized>(reader: &mut T) -> Result<String, NfcError> {
+    reader.set_property_bool(nfc1::Property::EasyFraming, false)?;
+    let uid = match reader.select_passive_target(&NTAG213_MODULATION)?.target_info {
+        // libnfc reports success with an empty target if nothing was selected.
+        TargetInfo::Iso14443a(x) if x.uid_len == 0 => return Err(NfcError::NoTag),
+        TargetInfo::Iso14443a(x) if x.uid_len != UID_LEN => {
+            warn!("Unexpected UID length: expected {}, got {}", UID_LEN, x.uid_len);
+            return Err(NfcError::InvalidTarget);
+        }
+        TargetInfo::Iso14443a(x) => {
+            let mut uid = [0u8; UID_LEN];
+            uid.copy_from_slice(&x.uid[..UID_LEN]);
+            hex::encode(uid)
+        }
+        _ => unreachable!(),
+    };
+    validate(reader)?;
+    Ok(uid)
+}
+
+fn transceive<T: Transport + ?Sized>(tx: &[u8],  reader: &mut T) -> Result<Vec<u8>, NfcError> {
+    transceive_bytes(tx, reader).map_err(|e| check_removed(e, tx, reader))
+}
+
+/// Send a command without probing for the tag when the reply is missing, for callers that expect no reply.
+fn transceive_bytes<T: Transport + ?Sized>(tx: &[u8], reader: &mut T) -> Result<Vec<u8>, NfcError> {
+    let recv = reader.transceive_bytes(tx, RX_LEN, nfc1::Timeout::Default)?;
+    if let [code] = recv[..]
+        && let Some(nak) = Nak::from_code(code)
+    {
+        warn!("Tag replied NAK to command {:#04X}: {}", tx[0], nak);
+        return Err(NfcError::Nak(nak));
+    }
+    Ok(recv)
+}
+
+/// A tag that left the field looks like a missing or garbled reply, so ask the reader whether it is still there.
+fn check_removed<T: Transport + ?Sized>(e: NfcError, tx: &[u8], reader: &mut T) -> NfcError {
+    match e {
+        NfcError::NfcError(e @ (nfc1::Error::Timeout | nfc1::Error::RfTransmissionError | nfc1::Error::TargetReleased)) => {
+            if reader.target_is_present().is_err() {
+                warn!("Tag removed from field during command {:#04X}: {}", tx[0], e);
+                return NfcError::TagRemoved;
+            }
+            NfcError::NfcError(e)
+        }
+        e => e,
+    }
+}
+
+fn transceive_write<T: Transport + ?Sized>(
+    tx: &[u8],
+    addr: usize,
+    reader: &mut T,
+) -> Result<(), NfcError> {
+    // The reader reports the tag's 4-bit ACK as a missing or garbled reply, so only other errors are worth a presence probe.
+    match transceive_bytes(tx, reader) {
+        Ok(_)=> Ok(()),
+        Err(NfcError::NfcError(ref e)) if (*e == nfc1::Error::RfTransmissionError || *e == nfc1::Error::Timeout) => Ok(()),
+        Err(e) => {
+            let e = check_removed(e, tx, reader);
+            warn!("Failed to write data to page {}: {}", addr, e);
+            Err(e)
+        }
+    }
+}
+
+fn get_version<T: Transport + ?Sized>(reader: &mut T) -> Result<Vec<u8>, NfcError> {
+    let tx = [cmd_code::GET_VERSION];
+    transceive(&tx, reader)
+}
+
+/// Check that the selected target is an NTAG21x card and return its variant.
+///
+/// # Arguments
+/// * `reader` - The NFC reader device with a selected target.
+///
+/// # Returns
+/// * `Ok(Variant)` with the detected chip variant.
+///
+/// # Errors
+/// * `NfcError::InvalidTarget` if the `GET_VERSION` response is not one of an NTAG21x.
+/// * `NfcError::NfcError` if there is an error during the NFC communication.
+/// * `NfcError::Nak` if the tag rejects a command, `NfcError::TagRemoved` if it leaves the field.
+pub fn validate<T: Transport + ?Sized>(reader: &mut T) -> Result<Variant, NfcError> {
+    let version = get_version(reader)?;
+    match Variant::from_version(&version) {
+        Some(variant) => Ok(variant),
+        None => {
+            warn!("GET_VERSION response is not an NTAG21x: {:?}", version);
+            Err(NfcError::InvalidTarget)
+        }
+    }
+}
+
+fn write_pages<T: Transport + ?Sized>(data: &[u8], page_addr: usize, page_addr_max: usize, reader: &mut T) -> Result<(), NfcError> {
+    if data.is_empty() {
+        return Ok(());
+    }
+
+    let page_num = data.len().div_ceil(4);
+    let page_end = page_addr + page_num - 1;
+    if page_addr < WRITE_PAGE_ADDR_MIN || page_end > page_addr_max {
+        warn!("Write address out of bounds: {} to {}", page_addr, page_end);
+        return Err(NfcError::InvalidArgument(format!(
+            "Write address out of bounds: {} to {}",
+            page_addr, page_end
+        )));
+    }
+    let mut addr = page_addr;
+    for chunk in data.chunks_exact(4) {
+        let mut tx = vec![cmd_code::WRITE, addr as u8];
+        tx.extend_from_slice(chunk);
+        transceive_write(&tx, addr, reader)?;
+        addr += 1;
+    }
+    let r = data.chunks_exact(4).remainder();
+    if !r.is_empty() {
+        let mut tx = vec![cmd_code::WRITE, addr as u8];
+        let mut last_page_data = [0u8; 4];
+        last_page_data[..r.len()].copy_from_slice(r);
+        tx.extend_from_slice(&last_page_data);
+        transceive_write(&tx, addr, reader)?;
+    }
+    Ok(())
+}
+
+/// Read data from the NTAG213 card.
+///
+/// # Arguments
+/// * `page_addr` - The starting page address to read from.
+/// * `byte_len` - The number of bytes to read.
+/// * `reader` - The NFC reader device to read from.
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` containing the read data if successful.
+///
+/// # Errors
+/// * `NfcError::InvalidArgument` if the page address or byte length is invalid.
+/// * `NfcError::UnexpectedResponse` if the response length is not as expected.
+/// * `NfcError::NfcError` if there is an error during the NFC communication.
+/// * `NfcError::Nak` if the tag rejects a command, `NfcError::TagRemoved` if it leaves the field.
+pub fn read<T: Transport + ?Sized>(page_addr: usize, byte_len: usize, reader: &mut T) -> Result<Vec<u8>, NfcError> {
+    read_pages(page_addr, byte_len, READ_PAGE_ADDR_MAX, reader)
+}
+
+fn read_pages<T: Transport + ?Sized>(page_addr: usize, byte_len: usize, page_addr_max: usize, reader: &mut T) -> Result<Vec<u8>, NfcError> {
+    if byte_len == 0 {
+        return Ok(Vec::new());
+    }
+    let page_num = byte_len.div_ceil(4);
+    let end_page = page_addr + page_num - 1;
+    if end_page > page_addr_max {
+        warn!("Read address out of bounds: {} to {}", page_addr, end_page);
+        return Err(NfcError::InvalidArgument(format!(
+            "Read address out of bounds: {} to {}",
+            page_addr, end_page
+        )));
+    }
+
+    let mut rs = Vec::with_capacity(byte_len);
+
+    let mut cur_page = page_addr;
+    while cur_page <= end_page {
+        let tx = [cmd_code::READ, cur_page as u8];
+        let recv = transceive(&tx, reader)?;
+        if recv.len() != 16 {
+            warn!("Unexpected response length: expected 16, got {}", recv.len());
+            return Err(NfcError::UnexpectedResponse(format!(
+                "Unexpected response length: expected 16, got {}",
+                recv.len()
+            )));
+        }
+        let byte_needed = (end_page - cur_page + 1).min(4) * 4;
+        rs.extend_from_slice(&recv[..byte_needed]);
+        cur_page += 4;
+    }
+    rs.truncate(byte_len);
+    Ok(rs)
+}
+
+fn url_to_msgtlv_payload(url: &str) -> Vec<u8> {
+    let (prefix, stripped_url) = uri::encode(url);
+    let mut rs = vec![prefix];
+    rs.extend_from_slice(stripped_url.as_bytes());
+    rs
+}
+
+/// Encode a URL as the NDEF TLVs written to user memory, which must fit in `user_len` bytes.
+fn url_to_write_bytes(url: &str, user_len: usize) -> Result<Vec<u8>, NfcError> {
+    use ndef_const::*;
+    const RECORD_HEADER_LEN: usize = 4;
+
+    let payload = url_to_msgtlv_payload(url);
+    // The lock control TLV, the message TLV tag and length, the record header and the terminator.
+    let write_len = TLV_HEADER_LEN + 2 + RECORD_HEADER_LEN + payload.len() + 1;
+    if payload.len() + RECORD_HEADER_LEN > TLV_SHORT_LEN_MAX || write_len > user_len {
+        warn!("URL too long: {} bytes encoded, {} bytes of user memory", write_len, user_len);
+        return Err(NfcError::InvalidArgument(format!(
+            "URL too long: {} bytes encoded, {} bytes of user memory",
+            write_len, user_len
+        )));
+    }
+    let mut rs = Vec::with_capacity(write_len);
+
+    rs.extend_from_slice(&TLV_HEADER);
+
+    rs.extend_from_slice(&[
+        TLV_NDEF_MESSAGE,
+        (payload.len() + RECORD_HEADER_LEN) as u8,
+        TLV_NDEF_RECORD,
+        TLV_NDEF_MESSAGE_TYPE_LEN, payload.len() as u8,
+        TLV_NDEF_RECORD_TYPE,
+    ]);
+    rs.extend_from_slice(&payload);
+
+    rs.push(TLV_TERMINATOR);
+    Ok(rs)
+}
+
+/// Find the NDEF message TLV in user memory and decode its first record if it is a URI record.
+fn parse_url_tlv(data: &[u8]) -> Result<Option<String>, NfcError> {
+    use ndef_const::*;
+
+    let truncated = || {
+        warn!("Truncated TLV in user memory");
+        NfcError::UnexpectedResponse("Truncated TLV in user memory".to_string())
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            TLV_NULL => i += 1,
+            TLV_TERMINATOR => return Ok(None),
+            tag => {
+                let (len, header_len) = match data.get(i + 1) {
+                    Some(&TLV_LONG_LEN) => {
+                        let len = data.get(i + 2..i + 4).ok_or_else(truncated)?;
+                        (u16::from_be_bytes([len[0], len[1]]) as usize, 4)
+                    }
+                    Some(&len) => (len as usize, 2),
+                    None => return Err(truncated()),
+                };
+                let value = data.get(i + header_len..i + header_len + len).ok_or_else(truncated)?;
+                if tag == TLV_NDEF_MESSAGE {
+                    return parse_url_record(value);
+                }
+                i += header_len + len;
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn parse_url_record(message: &[u8]) -> Result<Option<String>, NfcError> {
+    use ndef_const::*;
+
+    let Some(&header) = message.first() else {
+        return Ok(None);
+    };
+    if header & RECORD_TNF_MASK != RECORD_TNF_WELL_KNOWN || header & RECORD_SR == 0 {
+        return Ok(None);
+    }
+    let (Some(&type_len), Some(&payload_len)) = (message.get(1), message.get(2)) else {
+        return Ok(None);
+    };
+    let (type_len, payload_len) = (type_len as usize, payload_len as usize);
+    let (id_len, type_start) = if header & RECORD_IL != 0 {
+        (message.get(3).copied().unwrap_or(0) as usize, 4)
+    } else {
+        (0, 3)
+    };
+    let payload_start = type_start + type_len + id_len;
+    let record_type = message.get(type_start..type_start + type_len);
+    let payload = message.get(payload_start..payload_start + payload_len);
+    match (record_type, payload) {
+        (Some(&[TLV_NDEF_RECORD_TYPE]), Some(payload)) => uri::decode(payload).map(Some),
+        _ => Ok(None),
+    }
+}
+
+mod ndef_const {
+    pub const TLV_HEADER: [u8; 5] = [0x01, 0x03, 0xA0, 0x0C, 0x34];
+    pub const TLV_HEADER_LEN: usize = 5;
+
+    pub const TLV_NDEF_MESSAGE: u8 = 0x03;
+    pub const TLV_NDEF_MESSAGE_TYPE_LEN: u8 = 1;
+    pub const TLV_NDEF_RECORD: u8 = 0xD1;
+    pub const TLV_NDEF_RECORD_TYPE: u8 = b'U';
+
+    pub const TLV_NULL: u8 = 0x00;
+    pub const TLV_TERMINATOR: u8 = 0xFE;
+    pub const TLV_LONG_LEN: u8 = 0xFF;
+    pub const TLV_SHORT_LEN_MAX: usize = 0xFE;
+
+    pub const RECORD_TNF_MASK: u8 = 0x07;
+    pub const RECORD_TNF_WELL_KNOWN: u8 = 0x01;
+    pub const RECORD_SR: u8 = 0x10;
+    pub const RECORD_IL: u8 = 0x08;
+
+    pub const TLV_NDEF_MESSAGE_EMPTY: [u8; 3] = [TLV_NDEF_MESSAGE, 0x00, TLV_TERMINATOR];
+}
+
+/// Check that the one-time-programmable capability container can be programmed to `desired`.
+///
+/// Bits of the CC can only be set, never cleared, and none can change once the CC is locked.
+fn check_cc_compatible(current: &[u8], desired: &[u8; 4], locked: bool) -> Result<(), NfcError> {
+    let compatible = if locked {
+        current == desired
+    } else {
+        current.iter().zip(desired).all(|(c, d)| c & !d == 0)
+    };
+    if compatible {
+        Ok(())
+    } else {
+        warn!("Capability container {:02X?} cannot be programmed to {:02X?}, locked: {}", current, desired, locked);
+        Err(NfcError::InvalidTarget)
+    }
+}
+
+fn empty_ndef_write_bytes(variant: Variant) -> Vec<u8> {
+    use ndef_const::*;
+
+    // The lock control TLV points at the dynamic lock bytes, which only NTAG213 needs to declare.
+    let mut rs = match variant {
+        Variant::Ntag213 => TLV_HEADER.to_vec(),
+        Variant::Ntag215 | Variant::Ntag216 => Vec::new(),
+    };
+    rs.extend_from_slice(&TLV_NDEF_MESSAGE_EMPTY);
+    rs.resize(variant.user_len(), 0x00);
+    rs
+}
+
+/// Write a URL to the NTAG213 card.
+///
+/// The URL is written as is, with its prefix abbreviated by a URI identifier code where one matches.
+/// Use [`uri::to_ascii`] first for readers that do not handle non-ASCII URLs.
+///
+/// # Arguments
+/// * `url` - The URL to write.
+/// * `reader` - The NFC reader device to write to.
+///
+/// # Returns
+/// * `Ok(())` if the write operation was successful.
+///
+/// # Errors
+/// * `NfcError::InvalidArgument` if the URL does not fit in the user memory.
+/// * `NfcError::NfcError` if there is an error during the NFC communication.
+/// * `NfcError::Nak` if the tag rejects a command, `NfcError::TagRemoved` if it leaves the field.
+/// * `NfcError::UnexpectedResponse` if the response length is not as expected.
+pub fn write_url<T: Transport + ?Sized>(url: &str, reader: &mut T) -> Result<(), NfcError> {
+    let variant = Variant::Ntag213;
+    let data = url_to_write_bytes(url, variant.user_len())?;
+    // Never past user memory, the pages after it hold the lock bits and the password.
+    write_pages(&data, USER_PAGE_ADDR_MIN, variant.user_page_addr_max(), reader)
+}
+
+/// Read the URL written to the NTAG213 card.
+///
+/// # Arguments
+/// * `reader` - The NFC reader device to read from.
+///
+/// # Returns
+/// * `Ok(Some(String))` with the URL of the first NDEF record if it is a URI record.
+/// * `Ok(None)` if there is no NDEF message, or its first record is not a URI record.
+///
+/// # Errors
+/// * `NfcError::UnexpectedResponse` if the user memory or the URI record is malformed.
+/// * `NfcError::NfcError` if there is an error during the NFC communication.
+/// * `NfcError::Nak` if the tag rejects a command, `NfcError::TagRemoved` if it leaves the field.
+pub fn read_url<T: Transport + ?Sized>(reader: &mut T) -> Result<Option<String>, NfcError> {
+    let data = read(USER_PAGE_ADDR_MIN, Variant::Ntag213.user_len(), reader)?;
+    parse_url_tlv(&data)
+}
+
+/// Format the card as an empty NDEF tag.
+///
+/// The capability container is programmed for the detected chip size if it is not already,
+/// and the whole user memory is cleared to an empty NDEF message TLV.
+/// Since the capability container is one-time-programmable, it is checked before anything is written.
+///
+/// # Arguments
+/// * `reader` - The NFC reader device to write to.
+///
+/// # Returns
+/// * `Ok(())` if the card was formatted successfully.
+///
+/// # Errors
+/// * `NfcError::InvalidTarget` if the target is not a valid NTAG21x card,
+///   or its capability container cannot be programmed for NDEF.
+/// * `NfcError::NfcError` if there is an error during the NFC communication.
+/// * `NfcError::Nak` if the tag rejects a command, `NfcError::TagRemoved` if it leaves the field.
+/// * `NfcError::UnexpectedResponse` if the response length is not as expected.
+pub fn format<T: Transport + ?Sized>(reader: &mut T) -> Result<(), NfcError> {
+    const LOCK_CC_BIT: u8 = 1 << 3;
+
+    let variant = validate(reader)?;
+    let cc = variant.capability_container();
+
+    let head = read_pages(LOCK_PAGE_ADDR, 8, variant.page_addr_max(), reader)?;
+    let cc_locked = head[2] & LOCK_CC_BIT != 0;
+    let current_cc = &head[4..8];
+    check_cc_compatible(current_cc, &cc, cc_locked)?;
+    if current_cc != cc {
+        write_pages(&cc, CC_PAGE_ADDR, variant.page_addr_max(), reader)?;
+    }
+
+    let data = empty_ndef_write_bytes(variant);
+    write_pages(&data, USER_PAGE_ADDR_MIN, variant.user_page_addr_max(), reader)
+}
+
+/// Read the configuration pages (CFG0 and CFG1) of the card.
+///
+/// # Arguments
+/// * `reader` - The NFC reader device to read from.
+///
+/// # Returns
+/// * `Ok((Variant, TagConfig))` with the detected chip variant and its current configuration.
+///
+/// # Errors
+/// * `NfcError::InvalidTarget` if the target is not a valid NTAG21x card.
+/// * `NfcError::NfcError` if there is an error during the NFC communication.
+/// * `NfcError::Nak` if the tag rejects a command, `NfcError::TagRemoved` if it leaves the field.
+/// * `NfcError::UnexpectedResponse` if the response length is not as expected.
+pub fn read_config<T: Transport + ?Sized>(reader: &mut T) -> Result<(Variant, TagConfig), NfcError> {
+    let variant = validate(reader)?;
+    let data = read_pages(variant.cfg0_page_addr(), config::CONFIG_LEN, variant.page_addr_max(), reader)?;
+    Ok((variant, TagConfig::parse(&data)?))
+}
+
+/// Write the configuration pages (CFG0 and CFG1) of the card.
+///
+/// The configuration is validated against the detected chip variant before anything is written,
+/// and the write is refused if the configuration on the card is already locked by `CFGLCK`.
+/// CFG0 is written before CFG1, so a `CFGLCK` set by `config` only takes effect once both pages are written.
+///
+/// # Arguments
+/// * `config` - The configuration to write.
+/// * `reader` - The NFC reader device to write to.
+///
+/// # Returns
+/// * `Ok(())` if the operation was successful.
+///
+/// # Errors
+/// * `NfcError::InvalidArgument` if the configuration is illegal for the chip variant or the card is locked.
+/// * `NfcError::InvalidTarget` if the target is not a valid NTAG21x card.
+/// * `NfcError::NfcError` if there is an error during the NFC communication.
+/// * `NfcError::Nak` if the tag rejects a command, `NfcError::TagRemoved` if it leaves the field.
+/// * `NfcError::UnexpectedResponse` if the response length is not as expected.
+pub fn write_config<T: Transport + ?Sized>(config: &TagConfig, reader: &mut T) -> Result<(), NfcError> {
+    update_config(reader, |x| *x = *config)?;
+    Ok(())
+}
+
+/// Read the configuration pages, edit them with `f` and write them back.
+///
+/// See [`write_config`] for the validation performed before writing.
+///
+/// # Arguments
+/// * `reader` - The NFC reader device to use.
+/// * `f` - A closure editing the configuration read from the card.
+///
+/// # Returns
+/// * `Ok(TagConfig)` containing the configuration written to the card.
+///
+/// # Errors
+/// Same as [`write_config`].
+pub fn update_config<T: Transport + ?Sized, F>(reader: &mut T, f: F) -> Result<TagConfig, NfcError>
+where
+    F: FnOnce(&mut TagConfig),
+{
+    let (variant, current) = read_config(reader)?;
+    let mut config = current;
+    f(&mut config);
+
+    if config == current {
+        return Ok(config);
+    }
+    if current.cfglck {
+        warn!("Configuration is locked by CFGLCK");
+        return Err(NfcError::InvalidArgument("Configuration is locked by CFGLCK".to_string()));
+    }
+    config.validate(variant)?;
+    if config.cfglck {
+        warn!("Setting CFGLCK, the configuration pages will be permanently locked");
+    }
+
+    write_pages(&config.to_bytes(), variant.cfg0_page_addr(), variant.page_addr_max(), reader)?;
+    Ok(config)
+}
+
+/// Set the UID mirror for the NTAG213 card.
+///
+/// # Arguments
+/// * `page_addr` - The page address to set the UID mirror.
+/// * `byte_offset` - The byte offset within the page to set the UID mirror.
+/// * `reader` - The NFC reader device to write to.
+///
+/// # Returns
+/// * `Ok(())` if the operation was successful.
+///
+/// # Errors
+/// * `NfcError::InvalidArgument` if the page address or byte offset is out of bounds.
+/// * `NfcError::NfcError` if there is an error during the NFC communication.
+/// * `NfcError::Nak` if the tag rejects a command, `NfcError::TagRemoved` if it leaves the field.
+/// * `NfcError::UnexpectedResponse` if the response length is not as expected.
+pub fn set_uid_mirror<T: Transport + ?Sized>(page_addr: usize, byte_offset: usize, reader: &mut T) -> Result<(), NfcError> {
+    if page_addr > u8::MAX as usize || byte_offset > 0b11 {
+        warn!("Mirror position out of bounds: page {}, byte {}", page_addr, byte_offset);
+        return Err(NfcError::InvalidArgument(format!(
+            "Mirror position out of bounds: page {}, byte {}",
+            page_addr, byte_offset
+        )));
+    }
+
+    update_config(reader, |config| {
+        config.mirror_conf = MirrorConf::Uid;
+        config.mirror_page = page_addr as u8;
+        config.mirror_byte = byte_offset as u8;
+    })?;
+    Ok(())
+}
+
+pub fn with_card<T: Transport + ?Sized, F, R>(reader: &mut T, f: F) -> Result<R, NfcError>
+where
+    F: FnOnce(&mut T) -> Result<R, NfcError>,
+{
+    scan(reader)?;
+    let rs = f(reader);
+    reader.deselect_target()?;
+    rs
+}
+
+#[test]
+fn test_variant_from_version() {
+    assert_eq!(Variant::from_version(&[0x00, 0x04, 0x04, 0x02, 0x01, 0x00, 0x0F, 0x03]), Some(Variant::Ntag213));
+    assert_eq!(Variant::from_version(&[0x00, 0x04, 0x04, 0x02, 0x01, 0x00, 0x13, 0x03]), Some(Variant::Ntag216));
+    assert_eq!(Variant::from_version(&[0x00, 0x04, 0x03, 0x01, 0x01, 0x00, 0x0B, 0x03]), None);
+    assert_eq!(Variant::Ntag213.cfg0_page_addr(), 0x29);
+    assert_eq!(Variant::Ntag215.page_addr_max(), 0x86);
+}
+
+#[test]
+fn test_check_cc_compatible() {
+    let cc = Variant::Ntag213.capability_container();
+    assert!(check_cc_compatible(&[0x00, 0x00, 0x00, 0x00], &cc, false).is_ok());
+    assert!(check_cc_compatible(&cc, &cc, true).is_ok());
+    assert!(check_cc_compatible(&[0xE1, 0x10, 0x12, 0x0F], &cc, false).is_err());
+    assert!(check_cc_compatible(&[0x00, 0x00, 0x00, 0x00], &cc, true).is_err());
+}
+
+#[test]
+fn test_empty_ndef_write_bytes() {
+    let data = empty_ndef_write_bytes(Variant::Ntag213);
+    assert_eq!(data.len(), 144);
+    assert_eq!(&data[..8], &[0x01, 0x03, 0xA0, 0x0C, 0x34, 0x03, 0x00, 0xFE]);
+    let data = empty_ndef_write_bytes(Variant::Ntag216);
+    assert_eq!(data.len(), 888);
+    assert_eq!(&data[..4], &[0x03, 0x00, 0xFE, 0x00]);
+}
+
+#[test]
+fn test_url_tlv_roundtrip() {
+    for url in ["https://www.example.com/?uid=11223344556677", "urn:nfc:sn:1", "sms:+8610086"] {
+        let mut data = url_to_write_bytes(url, 144).unwrap();
+        data.resize(144, 0x00);
+        assert_eq!(parse_url_tlv(&data).unwrap().as_deref(), Some(url));
+    }
+    assert_eq!(parse_url_tlv(&empty_ndef_write_bytes(Variant::Ntag213)).unwrap(), None);
+    assert!(url_to_write_bytes(&"a".repeat(300), 888).is_err());
+}
+
+#[test]
+fn test_url_write_bytes_fit_user_memory() {
+    let user_len = Variant::Ntag213.user_len();
+    assert_eq!(user_len, 144);
+    assert_eq!(url_to_write_bytes(&"a".repeat(131), user_len).unwrap().len(), user_len);
+    assert!(matches!(url_to_write_bytes(&"a".repeat(132), user_len), Err(NfcError::InvalidArgument(_))));
+    assert!(matches!(url_to_write_bytes(&"a".repeat(140), user_len), Err(NfcError::InvalidArgument(_))));
+}
+
+#[test]
+fn test_write_probes_only_fatal_errors() {
+    use super::trace::{ReplayTransport, TraceEntry, TraceError, TraceFrame};
+
+    let entry = |frame| TraceEntry { timestamp: chrono::Utc::now(), duration_us: 0, frame };
+    let write = |addr: &str, result| entry(TraceFrame::Transceive { tx: format!("a2{addr}01020304"), result });
+    // The tag's 4-bit ACK reaches the reader as a timeout or a garbled frame, neither is worth a presence probe.
+    let mut replay = ReplayTransport::from_entries(vec![
+        write("04", Err(TraceError::Timeout)),
+        write("05", Err(TraceError::RfTransmissionError)),
+    ]);
+    write_pages(&[1, 2, 3, 4, 1, 2, 3, 4], 4, 39, &mut replay).unwrap();
+    assert_eq!(replay.remaining(), 0);
+
+    let mut replay = ReplayTransport::from_entries(vec![
+        write("04", Err(TraceError::TargetReleased)),
+        entry(TraceFrame::TargetIsPresent { result: Err(TraceError::TargetReleased) }),
+    ]);
+    assert!(matches!(write_pages(&[1, 2, 3, 4], 4, 39, &mut replay), Err(NfcError::TagRemoved)));
+    assert_eq!(replay.remaining(), 0);
+}
+
+#[test]
+fn test_ntag213() {
+    let mut ctx = nfc1::Context::new().unwrap();
+    let mut reader = super::open_reader(None, &mut ctx).unwrap();
+
+    let f= move |reader: &mut nfc1::Device| {
+        let uid = scan(reader)?;
+        println!("Scanned NTAG213 card with UID: {:?}", uid);
+        write_url("https://example.com?uid=11223344556677", reader)?;
+        let mirror_page = 0x04_usize + 7;
+        let byte_offset = 0_usize;
+        set_uid_mirror(mirror_page, byte_offset, reader)?;
+        let read_rs = read(4, 64, reader)?;
+        println!("Read data: {:?}", String::from_utf8_lossy(&read_rs));
+        Ok(())
+    };
+    with_card(&mut reader, f).unwrap();
+}
@@ -0,0 +1,388 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, LineWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use nfc1::{target_info::TargetInfo, Modulation, Property, Target, Timeout};
+use serde::{Deserialize, Serialize};
+
+use super::transport::Transport;
+
+/// One reader operation of a trace, stored as a line of JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub timestamp: DateTime<Utc>,
+    pub duration_us: u64,
+    pub frame: TraceFrame,
+}
+
+/// A reader operation with its arguments and result. Frames are hex encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TraceFrame {
+    SelectPassiveTarget { result: Result<TraceTarget, TraceError> },
+    DeselectTarget { result: Result<(), TraceError> },
+    SetPropertyBool { property: String, value: bool, result: Result<(), TraceError> },
+    Transceive { tx: String, result: Result<String, TraceError> },
+    TargetIsPresent { result: Result<(), TraceError> },
+}
+
+impl TraceFrame {
+    fn op(&self) -> &'static str {
+        match self {
+            TraceFrame::SelectPassiveTarget { .. } => "select_passive_target",
+            TraceFrame::DeselectTarget { .. } => "deselect_target",
+            TraceFrame::SetPropertyBool { .. } => "set_property_bool",
+            TraceFrame::Transceive { .. } => "transceive",
+            TraceFrame::TargetIsPresent { .. } => "target_is_present",
+        }
+    }
+}
+
+/// The ISO14443A target selected by the reader. Other targets are recorded with an empty UID.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceTarget {
+    pub uid: String,
+    pub atqa: [u8; 2],
+    pub sak: u8,
+}
+
+impl From<&Target> for TraceTarget {
+    fn from(target: &Target) -> Self {
+        match target.target_info {
+            TargetInfo::Iso14443a(x) => Self {
+                uid: hex::encode(&x.uid[..x.uid_len.min(x.uid.len())]),
+                atqa: x.atqa,
+                sak: x.sak,
+            },
+            _ => Self { uid: String::new(), atqa: [0; 2], sak: 0 },
+        }
+    }
+}
+
+impl TraceTarget {
+    fn to_target(&self, modulation: &Modulation) -> nfc1::Result<Target> {
+        let bytes = hex::decode(&self.uid).map_err(|_| nfc1::Error::Soft)?;
+        let mut uid = [0u8; 10];
+        if bytes.len() > uid.len() {
+            return Err(nfc1::Error::Soft);
+        }
+        uid[..bytes.len()].copy_from_slice(&bytes);
+        Ok(Target {
+            target_info: TargetInfo::Iso14443a(nfc1::target_info::Iso14443a {
+                atqa: self.atqa,
+                sak: self.sak,
+                uid,
+                uid_len: bytes.len(),
+                ats: [0u8; 254],
+                ats_len: 0,
+            }),
+            modulation: *modulation,
+        })
+    }
+}
+
+/// Serializable mirror of [`nfc1::Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TraceError {
+    Malloc,
+    Undefined(i32),
+    UndefinedModulationType,
+    NoDeviceFound,
+    Io,
+    InvalidArgument,
+    DeviceNotSupported,
+    NoSuchDeviceFound,
+    BufferOverflow,
+    Timeout,
+    OperationAborted,
+    NotImplemented,
+    TargetReleased,
+    RfTransmissionError,
+    MifareAuthFailed,
+    Soft,
+    Chip,
+}
+
+impl From<nfc1::Error> for TraceError {
+    fn from(e: nfc1::Error) -> Self {
+        match e {
+            nfc1::Error::Malloc => TraceError::Malloc,
+            nfc1::Error::Undefined(x) => TraceError::Undefined(x),
+            nfc1::Error::UndefinedModulationType => TraceError::UndefinedModulationType,
+            nfc1::Error::NoDeviceFound => TraceError::NoDeviceFound,
+            nfc1::Error::Io => TraceError::Io,
+            nfc1::Error::InvalidArgument => TraceError::InvalidArgument,
+            nfc1::Error::DeviceNotSupported => TraceError::DeviceNotSupported,
+            nfc1::Error::NoSuchDeviceFound => TraceError::NoSuchDeviceFound,
+            nfc1::Error::BufferOverflow => TraceError::BufferOverflow,
+            nfc1::Error::Timeout => TraceError::Timeout,
+            nfc1::Error::OperationAborted => TraceError::OperationAborted,
+            nfc1::Error::NotImplemented => TraceError::NotImplemented,
+            nfc1::Error::TargetReleased => TraceError::TargetReleased,
+            nfc1::Error::RfTransmissionError => TraceError::RfTransmissionError,
+            nfc1::Error::MifareAuthFailed => TraceError::MifareAuthFailed,
+            nfc1::Error::Soft => TraceError::Soft,
+            nfc1::Error::Chip => TraceError::Chip,
+        }
+    }
+}
+
+impl From<TraceError> for nfc1::Error {
+    fn from(e: TraceError) -> Self {
+        match e {
+            TraceError::Malloc => nfc1::Error::Malloc,
+            TraceError::Undefined(x) => nfc1::Error::Undefined(x),
+            TraceError::UndefinedModulationType => nfc1::Error::UndefinedModulationType,
+            TraceError::NoDeviceFound => nfc1::Error::NoDeviceFound,
+            TraceError::Io => nfc1::Error::Io,
+            TraceError::InvalidArgument => nfc1::Error::InvalidArgument,
+            TraceError::DeviceNotSupported => nfc1::Error::DeviceNotSupported,
+            TraceError::NoSuchDeviceFound => nfc1::Error::NoSuchDeviceFound,
+            TraceError::BufferOverflow => nfc1::Error::BufferOverflow,
+            TraceError::Timeout => nfc1::Error::Timeout,
+            TraceError::OperationAborted => nfc1::Error::OperationAborted,
+            TraceError::NotImplemented => nfc1::Error::NotImplemented,
+            TraceError::TargetReleased => nfc1::Error::TargetReleased,
+            TraceError::RfTransmissionError => nfc1::Error::RfTransmissionError,
+            TraceError::MifareAuthFailed => nfc1::Error::MifareAuthFailed,
+            TraceError::Soft => nfc1::Error::Soft,
+            TraceError::Chip => nfc1::Error::Chip,
+        }
+    }
+}
+
+/// A [`Transport`] that records every operation of the wrapped transport to a trace file.
+///
+/// Failing to write the trace is logged and never fails the operation itself.
+pub struct TraceRecorder<T: Transport> {
+    inner: T,
+    writer: LineWriter<File>,
+}
+
+impl<T: Transport> TraceRecorder<T> {
+    /// Wrap `inner`, appending the trace to the file at `path`.
+    pub fn new(inner: T, path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent_dir) = path.parent() {
+            fs::create_dir_all(parent_dir)?;
+        }
+        info!("recording NFC trace to {}", path.display());
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { inner, writer: LineWriter::new(file) })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn record<R>(&mut self, f: impl FnOnce(&mut T) -> R, frame: impl FnOnce(&R) -> TraceFrame) -> R {
+        let timestamp = Utc::now();
+        let start = Instant::now();
+        let rs = f(&mut self.inner);
+        let entry = TraceEntry {
+            timestamp,
+            duration_us: start.elapsed().as_micros() as u64,
+            frame: frame(&rs),
+        };
+        let written = serde_json::to_string(&entry)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(self.writer, "{}", line));
+        if let Err(e) = written {
+            warn!("failed to write NFC trace entry: {e}");
+        }
+        rs
+    }
+}
+
+fn trace_result<T, U>(rs: &nfc1::Result<T>, f: impl FnOnce(&T) -> U) -> Result<U, TraceError> {
+    rs.as_ref().map(f).map_err(|e| (*e).into())
+}
+
+impl<T: Transport> Transport for TraceRecorder<T> {
+    fn select_passive_target(&mut self, modulation: &Modulation) -> nfc1::Result<Target> {
+        self.record(
+            |x| x.select_passive_target(modulation),
+            |rs| TraceFrame::SelectPassiveTarget { result: trace_result(rs, |x| TraceTarget::from(x)) },
+        )
+    }
+
+    fn deselect_target(&mut self) -> nfc1::Result<()> {
+        self.record(
+            |x| x.deselect_target(),
+            |rs| TraceFrame::DeselectTarget { result: trace_result(rs, |_| ()) },
+        )
+    }
+
+    fn set_property_bool(&mut self, property: Property, value: bool) -> nfc1::Result<()> {
+        self.record(
+            |x| x.set_property_bool(property, value),
+            |rs| TraceFrame::SetPropertyBool {
+                property: format!("{:?}", property),
+                value,
+                result: trace_result(rs, |_| ()),
+            },
+        )
+    }
+
+    fn transceive_bytes(&mut self, tx: &[u8], rx_len: usize, timeout: Timeout) -> nfc1::Result<Vec<u8>> {
+        self.record(
+            |x| x.transceive_bytes(tx, rx_len, timeout),
+            |rs| TraceFrame::Transceive { tx: hex::encode(tx), result: trace_result(rs, |x| hex::encode(x)) },
+        )
+    }
+
+    fn target_is_present(&mut self) -> nfc1::Result<()> {
+        self.record(
+            |x| x.target_is_present(),
+            |rs| TraceFrame::TargetIsPresent { result: trace_result(rs, |_| ()) },
+        )
+    }
+}
+
+/// A [`Transport`] answering from a recorded trace instead of a reader.
+///
+/// Operations must be issued in the recorded order with the recorded arguments.
+/// A diverging operation, or one past the end of the trace, fails with `nfc1::Error::Soft`.
+pub struct ReplayTransport {
+    entries: VecDeque<TraceEntry>,
+}
+
+impl ReplayTransport {
+    pub fn from_entries(entries: Vec<TraceEntry>) -> Self {
+        Self { entries: entries.into() }
+    }
+
+    /// Load a trace file written by [`TraceRecorder`].
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut entries = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            entries.push(serde_json::from_str(&line).map_err(io::Error::from)?);
+        }
+        Ok(Self::from_entries(entries))
+    }
+
+    /// The number of recorded operations not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn next(&mut self, op: &str) -> nfc1::Result<TraceFrame> {
+        match self.entries.pop_front() {
+            Some(entry) if entry.frame.op() == op => Ok(entry.frame),
+            Some(entry) => {
+                warn!("NFC trace diverged: expected {}, got {}", entry.frame.op(), op);
+                Err(nfc1::Error::Soft)
+            }
+            None => {
+                warn!("NFC trace exhausted at {}", op);
+                Err(nfc1::Error::Soft)
+            }
+        }
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn select_passive_target(&mut self, modulation: &Modulation) -> nfc1::Result<Target> {
+        match self.next("select_passive_target")? {
+            TraceFrame::SelectPassiveTarget { result } => result?.to_target(modulation),
+            _ => unreachable!(),
+        }
+    }
+
+    fn deselect_target(&mut self) -> nfc1::Result<()> {
+        match self.next("deselect_target")? {
+            TraceFrame::DeselectTarget { result } => Ok(result?),
+            _ => unreachable!(),
+        }
+    }
+
+    fn set_property_bool(&mut self, property: Property, value: bool) -> nfc1::Result<()> {
+        match self.next("set_property_bool")? {
+            TraceFrame::SetPropertyBool { property: p, value: v, result } => {
+                if p != format!("{:?}", property) || v != value {
+                    warn!("NFC trace diverged: expected {} = {}, got {:?} = {}", p, v, property, value);
+                    return Err(nfc1::Error::Soft);
+                }
+                Ok(result?)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn transceive_bytes(&mut self, tx: &[u8], _rx_len: usize, _timeout: Timeout) -> nfc1::Result<Vec<u8>> {
+        match self.next("transceive")? {
+            TraceFrame::Transceive { tx: recorded, result } => {
+                if recorded != hex::encode(tx) {
+                    warn!("NFC trace diverged: expected TX {}, got {}", recorded, hex::encode(tx));
+                    return Err(nfc1::Error::Soft);
+                }
+                hex::decode(result?).map_err(|_| nfc1::Error::Soft)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn target_is_present(&mut self) -> nfc1::Result<()> {
+        match self.next("target_is_present")? {
+            TraceFrame::TargetIsPresent { result } => Ok(result?),
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn test_trace_record_and_replay() {
+    use super::ntag213;
+
+    let transceive = |tx: &str, result: Result<&str, TraceError>| TraceEntry {
+        timestamp: Utc::now(),
+        duration_us: 0,
+        frame: TraceFrame::Transceive { tx: tx.to_string(), result: result.map(str::to_string) },
+    };
+    let entries = vec![
+        TraceEntry {
+            timestamp: Utc::now(),
+            duration_us: 0,
+            frame: TraceFrame::SetPropertyBool { property: "EasyFraming".to_string(), value: false, result: Ok(()) },
+        },
+        TraceEntry {
+            timestamp: Utc::now(),
+            duration_us: 0,
+            frame: TraceFrame::SelectPassiveTarget {
+                result: Ok(TraceTarget { uid: "04a1b2c3d4e5f6".to_string(), atqa: [0x00, 0x44], sak: 0x00 }),
+            },
+        },
+        transceive("60", Ok("0004040201000f03")),
+        transceive("3004", Ok("0103a00c34030cd1010855046578616d")),
+        transceive("3008", Err(TraceError::Timeout)),
+        TraceEntry {
+            timestamp: Utc::now(),
+            duration_us: 0,
+            frame: TraceFrame::TargetIsPresent { result: Err(TraceError::Timeout) },
+        },
+    ];
+
+    let path = std::env::temp_dir().join(format!("nfc-trace-{}.jsonl", uuid::Uuid::new_v4()));
+    let mut recorder = TraceRecorder::new(ReplayTransport::from_entries(entries), &path).unwrap();
+    assert_eq!(ntag213::scan(&mut recorder).unwrap(), "04a1b2c3d4e5f6");
+    assert_eq!(ntag213::read(4, 4, &mut recorder).unwrap(), vec![0x01, 0x03, 0xA0, 0x0C]);
+    assert!(matches!(ntag213::read(8, 4, &mut recorder), Err(super::NfcError::TagRemoved)));
+    assert_eq!(recorder.into_inner().remaining(), 0);
+
+    let mut replay = ReplayTransport::open(&path).unwrap();
+    assert_eq!(replay.remaining(), 6);
+    assert_eq!(ntag213::scan(&mut replay).unwrap(), "04a1b2c3d4e5f6");
+    assert!(ntag213::read(5, 4, &mut replay).is_err());
+    fs::remove_file(&path).unwrap();
+}
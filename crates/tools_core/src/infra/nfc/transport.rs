@@ -0,0 +1,57 @@
+use nfc1::{Device, Modulation, Property, Target, Timeout};
+
+/// The reader operations used to talk to a tag.
+///
+/// Implemented by [`nfc1::Device`], and by [`super::trace::TraceRecorder`] and
+/// [`super::trace::ReplayTransport`] to record and replay the frames exchanged with a tag.
+pub trait Transport {
+    fn select_passive_target(&mut self, modulation: &Modulation) -> nfc1::Result<Target>;
+    fn deselect_target(&mut self) -> nfc1::Result<()>;
+    fn set_property_bool(&mut self, property: Property, value: bool) -> nfc1::Result<()>;
+    fn transceive_bytes(&mut self, tx: &[u8], rx_len: usize, timeout: Timeout) -> nfc1::Result<Vec<u8>>;
+    fn target_is_present(&mut self) -> nfc1::Result<()>;
+}
+
+impl Transport for Device {
+    fn select_passive_target(&mut self, modulation: &Modulation) -> nfc1::Result<Target> {
+        self.initiator_select_passive_target(modulation)
+    }
+
+    fn deselect_target(&mut self) -> nfc1::Result<()> {
+        self.initiator_deselect_target()
+    }
+
+    fn set_property_bool(&mut self, property: Property, value: bool) -> nfc1::Result<()> {
+        Device::set_property_bool(self, property, value)
+    }
+
+    fn transceive_bytes(&mut self, tx: &[u8], rx_len: usize, timeout: Timeout) -> nfc1::Result<Vec<u8>> {
+        self.initiator_transceive_bytes(tx, rx_len, timeout)
+    }
+
+    fn target_is_present(&mut self) -> nfc1::Result<()> {
+        self.initiator_target_is_present_any()
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for &mut T {
+    fn select_passive_target(&mut self, modulation: &Modulation) -> nfc1::Result<Target> {
+        (**self).select_passive_target(modulation)
+    }
+
+    fn deselect_target(&mut self) -> nfc1::Result<()> {
+        (**self).deselect_target()
+    }
+
+    fn set_property_bool(&mut self, property: Property, value: bool) -> nfc1::Result<()> {
+        (**self).set_property_bool(property, value)
+    }
+
+    fn transceive_bytes(&mut self, tx: &[u8], rx_len: usize, timeout: Timeout) -> nfc1::Result<Vec<u8>> {
+        (**self).transceive_bytes(tx, rx_len, timeout)
+    }
+
+    fn target_is_present(&mut self) -> nfc1::Result<()> {
+        (**self).target_is_present()
+    }
+}
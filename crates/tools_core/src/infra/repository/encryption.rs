@@ -0,0 +1,158 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use rusqlite::{Connection, ErrorCode, OptionalExtension};
+
+/// The first bytes of a plaintext SQLite database. An encrypted one starts with random
+/// bytes instead.
+const SQLITE_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+#[derive(Debug, thiserror::Error)]
+pub enum EncryptionError {
+    #[error(transparent)]
+    RusqliteError(#[from] rusqlite::Error),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("Task failed to execute: {0}")]
+    JoinError(#[from] tokio::task::JoinError),
+    #[error("SQLite was built without SQLCipher, enable the `sqlcipher` feature")]
+    Unsupported,
+    #[error("The database is encrypted and the passphrase is missing or wrong")]
+    WrongPassphrase,
+    #[error("The database is already encrypted")]
+    AlreadyEncrypted,
+    #[error("The passphrase must not be empty")]
+    EmptyPassphrase,
+}
+
+fn has_cipher(conn: &Connection) -> rusqlite::Result<bool> {
+    // Plain SQLite ignores unknown pragmas, so this is the only way to know the key
+    // would be used.
+    Ok(conn.query_row("PRAGMA cipher_version", [], |_| Ok(())).optional()?.is_some())
+}
+
+/// Whether SQLite was built with SQLCipher, so databases can be encrypted.
+pub fn is_supported() -> bool {
+    Connection::open_in_memory().and_then(|conn| has_cipher(&conn)).unwrap_or(false)
+}
+
+/// Open a database, unlocking it with a SQLCipher passphrase if one is given. A database
+/// that does not exist yet is created encrypted with the passphrase.
+///
+/// # Errors
+/// * `EncryptionError::WrongPassphrase` if the database is encrypted and the passphrase is
+///   missing or wrong.
+/// * `EncryptionError::Unsupported` if a passphrase is given but SQLite has no SQLCipher.
+pub fn open(db_path: impl AsRef<Path>, passphrase: Option<&str>) -> Result<Connection, EncryptionError> {
+    let conn = Connection::open(db_path)?;
+    if let Some(passphrase) = passphrase {
+        if !has_cipher(&conn)? {
+            return Err(EncryptionError::Unsupported);
+        }
+        conn.pragma_update(None, "key", passphrase)?;
+    }
+    // Nothing is decrypted until the first read, which fails if the key is wrong.
+    match conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(())) {
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::NotADatabase => {
+            Err(EncryptionError::WrongPassphrase)
+        }
+        rs => Ok(rs.map(|_| conn)?),
+    }
+}
+
+/// Whether a database file is encrypted, `false` if it does not exist or is empty.
+pub fn is_encrypted(db_path: impl AsRef<Path>) -> io::Result<bool> {
+    let mut file = match fs::File::open(db_path) {
+        Ok(x) => x,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let mut header = Vec::with_capacity(SQLITE_HEADER.len());
+    file.by_ref().take(SQLITE_HEADER.len() as u64).read_to_end(&mut header)?;
+    Ok(!header.is_empty() && header != SQLITE_HEADER)
+}
+
+/// Encrypt a plaintext database with a passphrase, replacing the file. There is no way
+/// back, and the database must not be open elsewhere.
+///
+/// The data is exported to a new file next to the database, which then takes its place,
+/// so the database is left as it was if anything fails.
+///
+/// # Errors
+/// * `EncryptionError::AlreadyEncrypted` if the database is encrypted.
+/// * `EncryptionError::Unsupported` if SQLite has no SQLCipher.
+pub async fn encrypt(db_path: &str, passphrase: &str) -> Result<(), EncryptionError> {
+    if passphrase.is_empty() {
+        return Err(EncryptionError::EmptyPassphrase);
+    }
+    let db_path = db_path.to_string();
+    let passphrase = passphrase.to_string();
+    tokio::task::spawn_blocking(move || {
+        if is_encrypted(&db_path)? {
+            return Err(EncryptionError::AlreadyEncrypted);
+        }
+        let conn = open(&db_path, None)?;
+        if !has_cipher(&conn)? {
+            return Err(EncryptionError::Unsupported);
+        }
+        let tmp_path = format!("{}.encrypting", db_path);
+        match fs::remove_file(&tmp_path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let export = || -> rusqlite::Result<()> {
+            conn.execute("ATTACH DATABASE ?1 AS encrypted KEY ?2", (&tmp_path, &passphrase))?;
+            conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+            conn.execute("DETACH DATABASE encrypted", [])?;
+            Ok(())
+        };
+        if let Err(e) = export() {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(e.into());
+        }
+        drop(conn);
+        fs::rename(&tmp_path, &db_path)?;
+        Ok(())
+    })
+    .await?
+}
+
+/// Change the passphrase of an encrypted database.
+///
+/// # Errors
+/// * `EncryptionError::WrongPassphrase` if `old` does not unlock the database.
+pub async fn rekey(db_path: &str, old: &str, new: &str) -> Result<(), EncryptionError> {
+    if new.is_empty() {
+        return Err(EncryptionError::EmptyPassphrase);
+    }
+    let db_path = db_path.to_string();
+    let (old, new) = (old.to_string(), new.to_string());
+    tokio::task::spawn_blocking(move || {
+        let conn = open(db_path, Some(&old))?;
+        conn.pragma_update(None, "rekey", &new)?;
+        Ok(())
+    })
+    .await?
+}
+
+#[test]
+fn test_is_encrypted() {
+    assert_eq!(is_supported(), cfg!(feature = "sqlcipher"));
+    let dir = std::env::temp_dir().join(format!("tools_core-encryption-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let path = dir.join("plain.sqlite");
+    assert!(!is_encrypted(&path).unwrap());
+    let conn = open(&path, None).unwrap();
+    conn.execute("CREATE TABLE t (x INTEGER)", []).unwrap();
+    drop(conn);
+    assert!(!is_encrypted(&path).unwrap());
+
+    let path = dir.join("random.sqlite");
+    fs::write(&path, [0x5a; 64]).unwrap();
+    assert!(is_encrypted(&path).unwrap());
+    assert!(matches!(open(&path, None), Err(EncryptionError::WrongPassphrase)));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
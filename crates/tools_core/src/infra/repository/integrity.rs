@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::ValueRef;
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::domain::sku::{normalize_sku, SkuError};
+use crate::infra::repository::encryption::{self, EncryptionError};
+use crate::infra::repository::sqlite::is_valid_table_name;
+
+/// Where quarantined rows are kept, as JSON, until someone looks at them.
+const QUARANTINE_TABLE: &str = "quarantine";
+const AUDIT_TABLE: &str = "audit_log";
+
+#[derive(Debug, thiserror::Error)]
+pub enum IntegrityError {
+    #[error(transparent)]
+    RusqliteError(#[from] rusqlite::Error),
+    #[error("Task failed to execute: {0}")]
+    JoinError(#[from] tokio::task::JoinError),
+    #[error(transparent)]
+    EncryptionError(#[from] EncryptionError),
+    #[error(transparent)]
+    SkuError(#[from] SkuError),
+    #[error("Table `{0}` does not exist")]
+    TableNotFound(String),
+    #[error("Table `{0}` is not a repository")]
+    NotARepository(String),
+    #[error("Row {1} of `{0}` does not exist")]
+    RowNotFound(String, i64),
+    #[error("SKU `{0}` is used by row {1}, quarantine one of the rows instead")]
+    SkuTaken(String, i64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IssueKind {
+    /// The id is not a UUID, so the row cannot be read at all.
+    InvalidId { id: String },
+    /// The id is the same UUID as an earlier row, written differently.
+    DuplicateId { id: String, first_rowid: i64 },
+    EmptySku,
+    /// The SKU looks like a product code with a wrong length or check digit.
+    InvalidSku { sku: String, message: String },
+    /// The SKU was stored before SKUs were normalized, so lookups by SKU miss it.
+    UnnormalizedSku { sku: String, normalized: String },
+    /// The SKU is the same as the one of an earlier row not in the trash once normalized.
+    DuplicateSku { sku: String, normalized: String, first_rowid: i64 },
+    /// A timestamp is missing, not an integer or out of range.
+    InvalidTimestamp { column: String },
+    /// An audit log entry of an item that is gone without having been purged or
+    /// quarantined, or of a collection whose table is gone.
+    OrphanedAuditEntry { collection: String, item_id: String },
+}
+
+/// A problem with a row of a table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Issue {
+    pub table: String,
+    pub rowid: i64,
+    #[serde(flatten)]
+    pub kind: IssueKind,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IntegrityReport {
+    /// The problems found by `PRAGMA integrity_check`, empty if the file is fine.
+    pub storage: Vec<String>,
+    /// The repository tables that were scanned.
+    pub tables: Vec<String>,
+    pub issues: Vec<Issue>,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.storage.is_empty() && self.issues.is_empty()
+    }
+}
+
+/// A fix for the row of an issue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Repair {
+    /// Move the row to the quarantine table, which works for any issue.
+    Quarantine,
+    /// Give the item a new id. Labels and tags with the old one no longer find it.
+    NewId,
+    /// Store the SKU in its normalized form, unless another item not in the trash has it.
+    NormalizeSku,
+}
+
+fn columns(conn: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1)")?;
+    let rows = stmt.query_map([table], |row| row.get(0))?;
+    rows.collect()
+}
+
+/// The tables with the columns of a `SqliteRepository`.
+fn repository_tables(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name")?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<Result<Vec<_>, _>>()?;
+    let mut rs = Vec::new();
+    for name in names {
+        let columns = columns(conn, &name)?;
+        if ["id", "sku", "metadata"].iter().all(|x| columns.iter().any(|c| c == x)) {
+            rs.push(name);
+        }
+    }
+    Ok(rs)
+}
+
+fn describe(value: ValueRef<'_>) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(x) => x.to_string(),
+        ValueRef::Real(x) => x.to_string(),
+        ValueRef::Text(x) => String::from_utf8_lossy(x).to_string(),
+        ValueRef::Blob(x) => format!("<{} byte blob>", x.len()),
+    }
+}
+
+fn scan_table(conn: &Connection, table: &str, issues: &mut Vec<Issue>) -> rusqlite::Result<()> {
+    let columns = columns(conn, table)?;
+    let timestamps: Vec<&str> = ["deleted_at", "created_at", "updated_at"]
+        .into_iter()
+        .filter(|x| columns.iter().any(|c| c == x))
+        .collect();
+    let sql = format!(
+        "SELECT rowid, id, sku{} FROM {} ORDER BY rowid",
+        timestamps.iter().map(|x| format!(", {}", x)).collect::<String>(),
+        table
+    );
+    let mut ids = HashMap::new();
+    let mut skus = HashMap::new();
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let rowid: i64 = row.get(0)?;
+        let mut push = |kind| issues.push(Issue { table: table.to_string(), rowid, kind });
+
+        let id = row.get_ref(1)?;
+        match id.as_str().ok().and_then(|x| Uuid::parse_str(x).ok()) {
+            None => push(IssueKind::InvalidId { id: describe(id) }),
+            Some(uuid) => match ids.get(&uuid) {
+                Some(first_rowid) => push(IssueKind::DuplicateId { id: describe(id), first_rowid: *first_rowid }),
+                None => {
+                    ids.insert(uuid, rowid);
+                }
+            },
+        }
+
+        // Items in the trash do not keep their SKU from being reused.
+        let active = timestamps.first() != Some(&"deleted_at") || row.get_ref(3)? == ValueRef::Null;
+        let sku = row.get_ref(2)?;
+        match sku.as_str().ok().map(normalize_sku) {
+            None => push(IssueKind::InvalidSku { sku: describe(sku), message: "not text".to_string() }),
+            Some(Err(SkuError::Empty)) => push(IssueKind::EmptySku),
+            Some(Err(e)) => push(IssueKind::InvalidSku { sku: describe(sku), message: e.to_string() }),
+            Some(Ok(normalized)) => {
+                let sku = describe(sku);
+                if let Some(first_rowid) = skus.get(&normalized).filter(|_| active) {
+                    push(IssueKind::DuplicateSku { sku, normalized, first_rowid: *first_rowid });
+                } else {
+                    if active {
+                        skus.insert(normalized.clone(), rowid);
+                    }
+                    if sku != normalized {
+                        push(IssueKind::UnnormalizedSku { sku, normalized });
+                    }
+                }
+            }
+        }
+
+        for (i, column) in timestamps.iter().enumerate() {
+            let valid = match row.get_ref(3 + i)? {
+                ValueRef::Null => *column == "deleted_at",
+                ValueRef::Integer(x) => DateTime::from_timestamp_millis(x).is_some(),
+                _ => false,
+            };
+            if !valid {
+                push(IssueKind::InvalidTimestamp { column: column.to_string() });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The ids of the items in the quarantine table, by table.
+fn quarantined_ids(conn: &Connection) -> rusqlite::Result<Vec<(String, String)>> {
+    if columns(conn, QUARANTINE_TABLE)?.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn.prepare(&format!(
+        "SELECT table_name, json_extract(data, '$.id') FROM {} WHERE json_type(data, '$.id') = 'text'",
+        QUARANTINE_TABLE
+    ))?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+fn scan_audit_log(conn: &Connection, tables: &[String], issues: &mut Vec<Issue>) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("SELECT id, collection, item_id, operation FROM {} ORDER BY id", AUDIT_TABLE))?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?)))?;
+    // The entries of each item, and its last operation.
+    let mut items: HashMap<(String, String), (Vec<i64>, String)> = HashMap::new();
+    for row in rows {
+        let (id, collection, item_id, operation) = row?;
+        let entry = items.entry((collection, item_id)).or_default();
+        entry.0.push(id);
+        entry.1 = operation;
+    }
+    // The history of a quarantined item is kept for whoever looks at it.
+    let quarantined = quarantined_ids(conn)?;
+    let mut orphaned: Vec<_> = Vec::new();
+    for ((collection, item_id), (entries, operation)) in items {
+        let exists = tables.contains(&collection)
+            && (operation == "purge"
+                || quarantined.iter().any(|(table, id)| *table == collection && *id == item_id)
+                || conn.prepare(&format!("SELECT 1 FROM {} WHERE id = ?1", collection))?.exists([&item_id])?);
+        if !exists {
+            orphaned.extend(entries.into_iter().map(|rowid| (rowid, collection.clone(), item_id.clone())));
+        }
+    }
+    orphaned.sort();
+    for (rowid, collection, item_id) in orphaned {
+        issues.push(Issue {
+            table: AUDIT_TABLE.to_string(),
+            rowid,
+            kind: IssueKind::OrphanedAuditEntry { collection, item_id },
+        });
+    }
+    Ok(())
+}
+
+/// Check the database file and scan every repository table, and the audit log if any,
+/// for rows that cannot be read or are not found by the repository.
+pub fn scan(conn: &Connection) -> Result<IntegrityReport, IntegrityError> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let storage = stmt.query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|x| x != "ok")
+        .collect();
+    let tables = repository_tables(conn)?;
+    let mut issues = Vec::new();
+    for table in &tables {
+        scan_table(conn, table, &mut issues)?;
+    }
+    if columns(conn, AUDIT_TABLE)?.iter().any(|x| x == "item_id") {
+        scan_audit_log(conn, &tables, &mut issues)?;
+    }
+    Ok(IntegrityReport { storage, tables, issues })
+}
+
+/// Apply a fix to a row of a table.
+///
+/// # Errors
+/// * `IntegrityError::TableNotFound` if there is no such table.
+/// * `IntegrityError::NotARepository` if the fix only applies to repository tables.
+/// * `IntegrityError::RowNotFound` if the row is gone.
+/// * `IntegrityError::SkuTaken` if the normalized SKU is the one of another item not in
+///   the trash.
+pub fn repair(conn: &mut Connection, table: &str, rowid: i64, repair: Repair) -> Result<(), IntegrityError> {
+    let columns = columns(conn, table)?;
+    if !is_valid_table_name(table) || columns.is_empty() {
+        return Err(IntegrityError::TableNotFound(table.to_string()));
+    }
+    if repair != Repair::Quarantine && !repository_tables(conn)?.iter().any(|x| x == table) {
+        return Err(IntegrityError::NotARepository(table.to_string()));
+    }
+    let tx = conn.transaction()?;
+    let exists = tx.prepare(&format!("SELECT 1 FROM {} WHERE rowid = ?1", table))?.exists([rowid])?;
+    if !exists {
+        return Err(IntegrityError::RowNotFound(table.to_string(), rowid));
+    }
+    match repair {
+        Repair::Quarantine => {
+            tx.execute(
+                &format!(
+                    "CREATE TABLE IF NOT EXISTS {} (
+                        id INTEGER PRIMARY KEY AUTOINCREMENT,
+                        table_name TEXT NOT NULL,
+                        row_id INTEGER NOT NULL,
+                        data TEXT NOT NULL,
+                        quarantined_at INTEGER NOT NULL
+                    )",
+                    QUARANTINE_TABLE
+                ),
+                [],
+            )?;
+            // JSON cannot hold blobs, keep them as hex.
+            let data = columns.iter()
+                .map(|x| format!("'{0}', CASE WHEN typeof({0}) = 'blob' THEN hex({0}) ELSE {0} END", x))
+                .collect::<Vec<_>>()
+                .join(", ");
+            tx.execute(
+                &format!(
+                    "INSERT INTO {} (table_name, row_id, data, quarantined_at) SELECT ?1, rowid, json_object({}), ?2 FROM {} WHERE rowid = ?3",
+                    QUARANTINE_TABLE, data, table
+                ),
+                (table, Utc::now().timestamp_millis(), rowid),
+            )?;
+            tx.execute(&format!("DELETE FROM {} WHERE rowid = ?1", table), [rowid])?;
+        }
+        Repair::NewId => {
+            tx.execute(&format!("UPDATE {} SET id = ?1 WHERE rowid = ?2", table), (Uuid::new_v4().to_string(), rowid))?;
+        }
+        Repair::NormalizeSku => {
+            let sku: Option<String> = tx.query_row(&format!("SELECT sku FROM {} WHERE rowid = ?1", table), [rowid], |row| row.get(0))
+                .optional()?
+                .flatten();
+            let sku = normalize_sku(sku.as_deref().unwrap_or_default())?;
+            // Only items not in the trash keep a SKU from being used by another one.
+            let active = if columns.iter().any(|x| x == "deleted_at") { "deleted_at IS NULL" } else { "1" };
+            let row_active: bool = tx.query_row(&format!("SELECT {} FROM {} WHERE rowid = ?1", active, table), [rowid], |row| row.get(0))?;
+            let taken: Option<i64> = tx.query_row(
+                &format!("SELECT rowid FROM {} WHERE sku = ?1 AND rowid != ?2 AND {}", table, active),
+                (&sku, rowid),
+                |row| row.get(0),
+            ).optional()?;
+            if let Some(taken) = taken.filter(|_| row_active) {
+                return Err(IntegrityError::SkuTaken(sku, taken));
+            }
+            tx.execute(&format!("UPDATE {} SET sku = ?1 WHERE rowid = ?2", table), (sku, rowid))?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Integrity checks and fixes of a database, on a new connection each time.
+pub struct SqliteIntegrity {
+    db_path: PathBuf,
+    passphrase: Option<String>,
+}
+
+impl SqliteIntegrity {
+    pub fn new(db_path: impl Into<PathBuf>) -> Self {
+        Self { db_path: db_path.into(), passphrase: None }
+    }
+
+    /// Unlock an encrypted database with a SQLCipher passphrase.
+    pub fn with_passphrase(mut self, passphrase: String) -> Self {
+        self.passphrase = Some(passphrase);
+        self
+    }
+
+    pub async fn check(&self) -> Result<IntegrityReport, IntegrityError> {
+        let db_path = self.db_path.clone();
+        let passphrase = self.passphrase.clone();
+        tokio::task::spawn_blocking(move || scan(&encryption::open(db_path, passphrase.as_deref())?)).await?
+    }
+
+    pub async fn repair(&self, table: &str, rowid: i64, fix: Repair) -> Result<(), IntegrityError> {
+        let db_path = self.db_path.clone();
+        let passphrase = self.passphrase.clone();
+        let table = table.to_string();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = encryption::open(db_path, passphrase.as_deref())?;
+            repair(&mut conn, &table, rowid, fix)
+        })
+        .await?
+    }
+}
+
+#[test]
+fn test_scan_and_repair() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "CREATE TABLE items (id TEXT PRIMARY KEY, sku TEXT NOT NULL UNIQUE, metadata TEXT NOT NULL, deleted_at INTEGER, created_at INTEGER NOT NULL, updated_at INTEGER NOT NULL);
+        INSERT INTO items VALUES ('6f1c1f7e-8a4b-4e6c-9d59-1c2b3a4d5e6f', 'FUMO0042', '{}', NULL, 0, 0);
+        INSERT INTO items VALUES ('not-a-uuid', 'FUMO0043', '{}', NULL, 0, 0);
+        INSERT INTO items VALUES ('6F1C1F7E-8A4B-4E6C-9D59-1C2B3A4D5E6F', ' ', '{}', NULL, 0, 0);
+        INSERT INTO items VALUES ('0b9a7c55-2f0e-4c4b-8f8a-3d5a0c8e9f10', 'FUMO 0042', '{}', NULL, 'yesterday', 0);
+        INSERT INTO items VALUES ('3c2b1a09-8f7e-4d6c-9b5a-4e3d2c1b0a98', '036000291452', '{}', NULL, 0, 0);
+        INSERT INTO items VALUES ('8e7d6c5b-4a39-4281-9f0e-1d2c3b4a5968', '4901234567890', '{}', NULL, 0, 0);
+        CREATE TABLE audit_log (id INTEGER PRIMARY KEY AUTOINCREMENT, collection TEXT NOT NULL, item_id TEXT NOT NULL, operation TEXT NOT NULL);
+        INSERT INTO audit_log (collection, item_id, operation) VALUES ('items', '6f1c1f7e-8a4b-4e6c-9d59-1c2b3a4d5e6f', 'add');
+        INSERT INTO audit_log (collection, item_id, operation) VALUES ('items', '11111111-1111-4111-8111-111111111111', 'add');
+        INSERT INTO audit_log (collection, item_id, operation) VALUES ('items', '22222222-2222-4222-8222-222222222222', 'purge');
+        INSERT INTO audit_log (collection, item_id, operation) VALUES ('dropped', '6f1c1f7e-8a4b-4e6c-9d59-1c2b3a4d5e6f', 'add');",
+    ).unwrap();
+
+    let report = scan(&conn).unwrap();
+    assert!(report.storage.is_empty());
+    assert_eq!(report.tables, ["items"]);
+    let issues: Vec<_> = report.issues.iter().map(|x| (x.table.as_str(), x.rowid, &x.kind)).collect();
+    assert_eq!(issues, [
+        ("items", 2, &IssueKind::InvalidId { id: "not-a-uuid".to_string() }),
+        ("items", 3, &IssueKind::DuplicateId { id: "6F1C1F7E-8A4B-4E6C-9D59-1C2B3A4D5E6F".to_string(), first_rowid: 1 }),
+        ("items", 3, &IssueKind::EmptySku),
+        ("items", 4, &IssueKind::DuplicateSku { sku: "FUMO 0042".to_string(), normalized: "FUMO0042".to_string(), first_rowid: 1 }),
+        ("items", 4, &IssueKind::InvalidTimestamp { column: "created_at".to_string() }),
+        ("items", 5, &IssueKind::UnnormalizedSku { sku: "036000291452".to_string(), normalized: "0036000291452".to_string() }),
+        ("items", 6, &IssueKind::InvalidSku {
+            sku: "4901234567890".to_string(),
+            message: SkuError::InvalidCheckDigit("4901234567890".to_string(), '4').to_string(),
+        }),
+        ("audit_log", 2, &IssueKind::OrphanedAuditEntry { collection: "items".to_string(), item_id: "11111111-1111-4111-8111-111111111111".to_string() }),
+        ("audit_log", 4, &IssueKind::OrphanedAuditEntry { collection: "dropped".to_string(), item_id: "6f1c1f7e-8a4b-4e6c-9d59-1c2b3a4d5e6f".to_string() }),
+    ]);
+
+    repair(&mut conn, "items", 2, Repair::NewId).unwrap();
+    repair(&mut conn, "items", 3, Repair::Quarantine).unwrap();
+    repair(&mut conn, "items", 4, Repair::Quarantine).unwrap();
+    repair(&mut conn, "items", 5, Repair::NormalizeSku).unwrap();
+    repair(&mut conn, "items", 6, Repair::Quarantine).unwrap();
+    repair(&mut conn, "audit_log", 2, Repair::Quarantine).unwrap();
+    repair(&mut conn, "audit_log", 4, Repair::Quarantine).unwrap();
+    assert!(scan(&conn).unwrap().is_ok());
+
+    let data: String = conn.query_row("SELECT data FROM quarantine WHERE row_id = 4", [], |row| row.get(0)).unwrap();
+    assert!(data.contains(r#""created_at":"yesterday""#));
+    assert!(matches!(repair(&mut conn, "items", 4, Repair::Quarantine), Err(IntegrityError::RowNotFound(..))));
+    assert!(matches!(repair(&mut conn, "audit_log", 1, Repair::NewId), Err(IntegrityError::NotARepository(_))));
+    assert!(matches!(repair(&mut conn, "nope", 1, Repair::Quarantine), Err(IntegrityError::TableNotFound(_))));
+}
+
+#[test]
+fn test_repair_sku_conflicts() {
+    let mut conn = Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        "CREATE TABLE items (id TEXT PRIMARY KEY, sku TEXT NOT NULL, metadata TEXT NOT NULL, deleted_at INTEGER, created_at INTEGER NOT NULL, updated_at INTEGER NOT NULL);
+        INSERT INTO items VALUES ('3c2b1a09-8f7e-4d6c-9b5a-4e3d2c1b0a98', '036000291452', '{}', NULL, 0, 0);
+        INSERT INTO items VALUES ('8e7d6c5b-4a39-4281-9f0e-1d2c3b4a5968', '0036000291452', '{}', NULL, 0, 0);
+        INSERT INTO items VALUES ('6f1c1f7e-8a4b-4e6c-9d59-1c2b3a4d5e6f', '0036000291452', '{}', 0, 0, 0);
+        CREATE TABLE audit_log (id INTEGER PRIMARY KEY AUTOINCREMENT, collection TEXT NOT NULL, item_id TEXT NOT NULL, operation TEXT NOT NULL);
+        INSERT INTO audit_log (collection, item_id, operation) VALUES ('items', '8e7d6c5b-4a39-4281-9f0e-1d2c3b4a5968', 'add');",
+    ).unwrap();
+
+    // The item in the trash does not take the SKU.
+    let issues: Vec<_> = scan(&conn).unwrap().issues.into_iter().map(|x| (x.rowid, x.kind)).collect();
+    assert_eq!(issues, [
+        (1, IssueKind::UnnormalizedSku { sku: "036000291452".to_string(), normalized: "0036000291452".to_string() }),
+        (2, IssueKind::DuplicateSku { sku: "0036000291452".to_string(), normalized: "0036000291452".to_string(), first_rowid: 1 }),
+    ]);
+    assert!(matches!(
+        repair(&mut conn, "items", 1, Repair::NormalizeSku),
+        Err(IntegrityError::SkuTaken(sku, 2)) if sku == "0036000291452"
+    ));
+
+    // The history of a quarantined item is not orphaned.
+    repair(&mut conn, "items", 2, Repair::Quarantine).unwrap();
+    repair(&mut conn, "items", 1, Repair::NormalizeSku).unwrap();
+    assert!(scan(&conn).unwrap().is_ok());
+}
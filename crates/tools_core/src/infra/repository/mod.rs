@@ -1 +1,3 @@
+pub mod encryption;
+pub mod integrity;
 pub mod sqlite;
\ No newline at end of file
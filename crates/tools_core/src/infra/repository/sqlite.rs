@@ -1,11 +1,21 @@
-use std::{fs, path::PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError, Weak};
+use std::time::Duration;
+use std::{fs, path::PathBuf, thread};
 
 use async_trait::async_trait;
-use rusqlite::Connection;
+use chrono::{DateTime, Utc};
+use log::warn;
+use rusqlite::{Connection, OptionalExtension, Transaction};
 use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, RwLock};
 use uuid::Uuid;
 
-use crate::domain::repository::{Repository, Storable};
+use crate::domain::repository::{ItemTimestamp, Observable, Repository, RepositoryEvent, Storable, TimeRange, Trash};
+use crate::domain::schema::{MetadataSchema, SchemaError};
+use crate::domain::sku::{normalize_sku, SkuError};
+use crate::infra::audit::{AuditOperation, AuditRecorder, RecordedRepository};
+use crate::infra::repository::encryption::{self, EncryptionError};
 
 #[derive(Debug, thiserror::Error)]
 pub enum SqliteRepositoryError {
@@ -19,54 +29,526 @@ pub enum SqliteRepositoryError {
     ParseError(String),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    SkuError(#[from] SkuError),
+    #[error(transparent)]
+    SchemaError(#[from] SchemaError),
+    #[error("Item `{0}` does not exist")]
+    ItemNotFound(Uuid),
+    #[error(transparent)]
+    EncryptionError(#[from] EncryptionError),
+    #[error("SKU `{0}` is the same as `{1}` once normalized, quarantine one of them with the database check")]
+    SkuConflict(String, String),
+    #[error("SKU `{0}` is used by another item")]
+    SkuTaken(String),
+    #[error("The repository is closed")]
+    Closed,
+}
+
+/// Whether a name can be used as a table without quoting: a letter or `_` followed by
+/// letters, digits and `_`, not starting with the reserved `sqlite_` prefix.
+pub fn is_valid_table_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|x| x.is_ascii_alphabetic() || x == '_')
+        && chars.all(|x| x.is_ascii_alphanumeric() || x == '_')
+        && !name.to_ascii_lowercase().starts_with("sqlite_")
+}
+
+const EVENT_CAPACITY: usize = 64;
+
+/// Publishes the changes of a repository, shared with its watcher thread.
+struct Notifier {
+    sender: broadcast::Sender<RepositoryEvent<SqliteRepositoryItem>>,
+    /// Writes of this repository started and finished, so the watcher can tell them
+    /// from writes of other connections.
+    started: AtomicU64,
+    finished: AtomicU64,
+}
+
+impl Notifier {
+    fn new() -> Self {
+        Self {
+            sender: broadcast::channel(EVENT_CAPACITY).0,
+            started: AtomicU64::new(0),
+            finished: AtomicU64::new(0),
+        }
+    }
+
+    fn begin_write(&self) {
+        self.started.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn end_write(&self, events: Vec<RepositoryEvent<SqliteRepositoryItem>>) {
+        self.finished.fetch_add(1, Ordering::SeqCst);
+        for event in events {
+            // Nobody listening is fine.
+            let _ = self.sender.send(event);
+        }
+    }
+}
+
+/// Set to stop the watcher thread of a repository, which checks it before each poll.
+#[derive(Default)]
+struct StopFlag {
+    stopped: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl StopFlag {
+    fn stop(&self) {
+        *self.stopped.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        self.wake.notify_all();
+    }
+
+    /// Wait for `timeout` unless stopped meanwhile, returning whether it is stopped.
+    fn wait(&self, timeout: Duration) -> bool {
+        let stopped = self.stopped.lock().unwrap_or_else(PoisonError::into_inner);
+        *self.wake.wait_timeout_while(stopped, timeout, |x| !*x)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+    }
 }
 
+struct Watcher {
+    stop: Arc<StopFlag>,
+    thread: thread::JoinHandle<()>,
+}
+
+/// Poll `PRAGMA data_version`, which changes when another connection commits, until the
+/// repository is closed or dropped. A change is ours if a write of the repository started
+/// since the last poll or was still running at it.
+fn watch_data_version(conn: Connection, notifier: Weak<Notifier>, interval: Duration, stop: &StopFlag) {
+    let data_version = |conn: &Connection| conn.query_row("PRAGMA data_version", [], |row| row.get::<_, i64>(0));
+    let Ok(mut version) = data_version(&conn) else {
+        return;
+    };
+    let (mut started, mut finished) = (0, 0);
+    loop {
+        if stop.wait(interval) {
+            return;
+        }
+        let Some(notifier) = notifier.upgrade() else {
+            return;
+        };
+        let now_started = notifier.started.load(Ordering::SeqCst);
+        let now_version = match data_version(&conn) {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("Failed to read the data version: {}", e);
+                continue;
+            }
+        };
+        let now_finished = notifier.finished.load(Ordering::SeqCst);
+        if now_version != version && now_started == started && started == finished {
+            let _ = notifier.sender.send(RepositoryEvent::External);
+        }
+        (version, started, finished) = (now_version, now_started, now_finished);
+    }
+}
+
+/// Normalize the SKUs stored before SKUs were normalized, so lookups by SKU find them.
+/// SKUs that are not valid product codes are left as they are.
+///
+/// # Errors
+/// * `SqliteRepositoryError::SkuConflict` if two SKUs are the same once normalized.
+fn normalize_skus(conn: &Connection, table_name: &str) -> Result<(), SqliteRepositoryError> {
+    let skus = {
+        let mut stmt = conn.prepare(&format!("SELECT rowid, sku, deleted_at IS NULL FROM {}", table_name))?;
+        stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, bool>(2)?)))?
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    let sql_find = format!("SELECT 1 FROM {} WHERE sku = ?1 AND rowid != ?2 AND deleted_at IS NULL", table_name);
+    let sql_update = format!("UPDATE {} SET sku = ?1 WHERE rowid = ?2", table_name);
+    for (rowid, sku, active) in skus {
+        let Ok(normalized) = normalize_sku(&sku) else {
+            continue;
+        };
+        if normalized == sku {
+            continue;
+        }
+        // Items in the trash can share a SKU with any other item.
+        if active && conn.prepare(&sql_find)?.exists((&normalized, rowid))? {
+            warn!("SKU `{}` of `{}` is the same as an existing SKU once normalized", sku, table_name);
+            return Err(SqliteRepositoryError::SkuConflict(sku, normalized));
+        }
+        conn.execute(&sql_update, (&normalized, rowid))?;
+    }
+    Ok(())
+}
+
+/// Report a SKU taken by another item in its own error, rather than as a failed constraint.
+fn sku_taken(e: rusqlite::Error, sku: &str) -> SqliteRepositoryError {
+    match &e {
+        rusqlite::Error::SqliteFailure(x, _) if x.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE => {
+            SqliteRepositoryError::SkuTaken(sku.to_string())
+        }
+        _ => e.into(),
+    }
+}
+
+fn create_table_sql(table_name: &str) -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (id TEXT PRIMARY KEY, sku TEXT NOT NULL, metadata TEXT NOT NULL, deleted_at INTEGER, created_at INTEGER NOT NULL, updated_at INTEGER NOT NULL)",
+        table_name
+    )
+}
+
+/// Drop the `UNIQUE` constraint of the SKU column of tables from before the trash, by
+/// copying the rows to a new table. Rowids are kept.
+fn drop_sku_constraint(conn: &Connection, table_name: &str) -> rusqlite::Result<()> {
+    let sql = format!("SELECT 1 FROM pragma_index_list('{}') WHERE origin = 'u'", table_name);
+    if !conn.prepare(&sql)?.exists([])? {
+        return Ok(());
+    }
+    // No collection can be named with a `-`, so this cannot be one.
+    let new_table = format!("\"{}-migrating\"", table_name);
+    conn.execute(&format!("DROP TABLE IF EXISTS {}", new_table), [])?;
+    conn.execute(&create_table_sql(&new_table), [])?;
+    conn.execute(&format!("INSERT INTO {} (rowid, {2}) SELECT rowid, {2} FROM {}", new_table, table_name, COLUMNS), [])?;
+    conn.execute(&format!("DROP TABLE {}", table_name), [])?;
+    conn.execute(&format!("ALTER TABLE {} RENAME TO {}", new_table, table_name), [])?;
+    Ok(())
+}
+
+/// Add a column to a table created by an older version.
+fn ensure_column(conn: &Connection, table_name: &str, column: &str, decl: &str) -> rusqlite::Result<()> {
+    let sql = format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1", table_name);
+    if !conn.prepare(&sql)?.exists([column])? {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table_name, column, decl), [])?;
+    }
+    Ok(())
+}
+
+const COLUMNS: &str = "id, sku, metadata, deleted_at, created_at, updated_at";
+
 pub struct SqliteRepository {
     db_path: String,
     table_name: String,
+    notifier: Arc<Notifier>,
+    schema: Option<MetadataSchema>,
+    passphrase: Option<String>,
+    /// Held for reading by every operation, and set by `close`.
+    closed: RwLock<bool>,
+    watcher: Mutex<Option<Watcher>>,
     sql_get_by_id: String,
     sql_get_by_sku: String,
     sql_add: String,
+    sql_update: String,
     sql_delete: String,
     sql_list: String,
+    sql_list_with_deleted: String,
+    sql_get_deleted: String,
+    sql_restore: String,
+    sql_purge: String,
+    sql_list_created_between: String,
+    sql_list_updated_between: String,
 }
 
 impl SqliteRepository {
     pub async fn new(db_path: &str, table_name: String) -> Self {
-        let sql_get_by_id = format!("SELECT id, sku, metadata FROM {} WHERE id = ?1", table_name);
-        let sql_get_by_sku = format!("SELECT id, sku, metadata FROM {} WHERE sku = ?1", table_name);
-        let sql_add = format!("INSERT INTO {} (id, sku, metadata) VALUES (?1, ?2, ?3)", table_name);
-        let sql_delete = format!("DELETE FROM {} WHERE id = ?1", table_name);
-        let sql_list = format!("SELECT id, sku, metadata FROM {}", table_name);
-        let rs = SqliteRepository {
+        let sql_get_by_id = format!("SELECT {} FROM {} WHERE id = ?1 AND deleted_at IS NULL", COLUMNS, table_name);
+        let sql_get_by_sku = format!("SELECT {} FROM {} WHERE sku = ?1 AND deleted_at IS NULL", COLUMNS, table_name);
+        let sql_add = format!("INSERT INTO {} (id, sku, metadata, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5)", table_name);
+        let sql_update = format!("UPDATE {} SET sku = ?2, metadata = ?3, updated_at = ?4 WHERE id = ?1 AND deleted_at IS NULL", table_name);
+        let sql_delete = format!("UPDATE {} SET deleted_at = ?2 WHERE id = ?1 AND deleted_at IS NULL", table_name);
+        let sql_list = format!("SELECT {} FROM {} WHERE deleted_at IS NULL ORDER BY created_at, rowid", COLUMNS, table_name);
+        let sql_list_with_deleted = format!("SELECT {} FROM {} ORDER BY created_at, rowid", COLUMNS, table_name);
+        let sql_get_deleted = format!("SELECT {} FROM {} WHERE id = ?1 AND deleted_at IS NOT NULL", COLUMNS, table_name);
+        let sql_restore = format!("UPDATE {} SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL", table_name);
+        let sql_purge = format!("DELETE FROM {} WHERE id = ?1 AND deleted_at IS NOT NULL", table_name);
+        let sql_list_between = |column: &str| format!(
+            "SELECT {} FROM {} WHERE deleted_at IS NULL AND {2} >= ?1 AND {2} < ?2 ORDER BY {2}, rowid",
+            COLUMNS, table_name, column
+        );
+        let sql_list_created_between = sql_list_between("created_at");
+        let sql_list_updated_between = sql_list_between("updated_at");
+        SqliteRepository {
             db_path: db_path.to_string(),
             table_name,
+            notifier: Arc::new(Notifier::new()),
+            schema: None,
+            passphrase: None,
+            closed: RwLock::new(false),
+            watcher: Mutex::default(),
             sql_get_by_id,
             sql_get_by_sku,
             sql_add,
+            sql_update,
             sql_delete,
             sql_list,
-        };
+            sql_list_with_deleted,
+            sql_get_deleted,
+            sql_restore,
+            sql_purge,
+            sql_list_created_between,
+            sql_list_updated_between,
+        }
+    }
+
+    /// Validate the metadata of added and updated items against a schema.
+    pub fn with_schema(mut self, schema: MetadataSchema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Unlock an encrypted database with a SQLCipher passphrase, or create it encrypted if
+    /// it does not exist yet.
+    pub fn with_passphrase(mut self, passphrase: String) -> Self {
+        self.passphrase = Some(passphrase);
+        self
+    }
+
+    pub fn schema(&self) -> Option<&MetadataSchema> {
+        self.schema.as_ref()
+    }
+
+    /// Normalize the SKU and validate the metadata of an item about to be written.
+    fn prepare(&self, item: SqliteRepositoryItem) -> Result<SqliteRepositoryItem, SqliteRepositoryError> {
+        if let Some(schema) = &self.schema {
+            schema.validate(&item.metadata)?;
+        }
+        Ok(SqliteRepositoryItem { sku: normalize_sku(&item.sku)?, ..item })
+    }
+
+    /// Run a query on a new connection in a blocking task.
+    async fn read<R, F>(&self, f: F) -> Result<R, SqliteRepositoryError>
+    where
+        R: Send + 'static,
+        F: FnOnce(Connection) -> Result<R, SqliteRepositoryError> + Send + 'static,
+    {
+        let closed = self.closed.read().await;
+        if *closed {
+            return Err(SqliteRepositoryError::Closed);
+        }
+        let db_path = self.db_path.clone();
+        let passphrase = self.passphrase.clone();
+        tokio::task::spawn_blocking(move || f(encryption::open(db_path, passphrase.as_deref())?)).await?
+    }
+
+    /// Run a write in a transaction on a new connection in a blocking task, then publish
+    /// the events of its result. Nothing is committed if `f` fails.
+    async fn write<R, F, E>(&self, f: F, events: E) -> Result<R, SqliteRepositoryError>
+    where
+        R: Send + 'static,
+        F: FnOnce(&Transaction) -> Result<R, SqliteRepositoryError> + Send + 'static,
+        E: FnOnce(&R) -> Vec<RepositoryEvent<SqliteRepositoryItem>>,
+    {
+        self.notifier.begin_write();
+        let rs = self.read(move |mut conn| {
+            let tx = conn.transaction()?;
+            let rs = f(&tx)?;
+            tx.commit()?;
+            Ok(rs)
+        }).await;
+        self.notifier.end_write(rs.as_ref().map(events).unwrap_or_default());
         rs
     }
 
+    /// Create the table, or bring one created by an older version up to date.
+    ///
+    /// Rows from before the creation and update times were recorded get the time of the
+    /// migration, and keep their insertion order. SKUs stored before SKUs were normalized
+    /// are normalized.
+    ///
+    /// SKUs are unique among the items not in the trash, so an item in the trash does not
+    /// keep its SKU from being reused.
+    ///
+    /// # Errors
+    /// * `SqliteRepositoryError::SkuConflict` if two stored SKUs are the same once
+    ///   normalized. Nothing is migrated until one of them is repaired.
     pub async fn create_table(&self) -> Result<(), SqliteRepositoryError> {
-        let db_path = PathBuf::from(self.db_path.clone());
+        if let Some(parent_dir) = PathBuf::from(&self.db_path).parent() {
+            fs::create_dir_all(parent_dir)?
+        }
         let table_name = self.table_name.clone();
-
-        tokio::task::spawn_blocking(move || {
-            if let Some(parent_dir) = db_path.as_path().parent() {
-                fs::create_dir_all(parent_dir)?
+        self.read(move |mut conn| {
+            let tx = conn.transaction()?;
+            tx.execute(&create_table_sql(&table_name), [])?;
+            ensure_column(&tx, &table_name, "deleted_at", "INTEGER")?;
+            ensure_column(&tx, &table_name, "created_at", "INTEGER")?;
+            ensure_column(&tx, &table_name, "updated_at", "INTEGER")?;
+            tx.execute(&format!("UPDATE {} SET created_at = ?1 WHERE created_at IS NULL", table_name), [Utc::now().timestamp_millis()])?;
+            tx.execute(&format!("UPDATE {} SET updated_at = created_at WHERE updated_at IS NULL", table_name), [])?;
+            drop_sku_constraint(&tx, &table_name)?;
+            for column in ["created_at", "updated_at"] {
+                tx.execute(&format!("CREATE INDEX IF NOT EXISTS {0}_{1} ON {0} ({1})", table_name, column), [])?;
             }
-            let conn = Connection::open(db_path)?;
-            let sql = format!(
-                "CREATE TABLE IF NOT EXISTS {} (id TEXT PRIMARY KEY, sku TEXT NOT NULL UNIQUE, metadata TEXT NOT NULL)",
-                table_name
-            );
-            conn.execute(&sql, [])?;
+            normalize_skus(&tx, &table_name)?;
+            tx.execute(&format!("CREATE UNIQUE INDEX IF NOT EXISTS {0}_sku ON {0} (sku) WHERE deleted_at IS NULL", table_name), [])?;
+            tx.commit()?;
             Ok(())
-        })
-        .await?
+        }).await
+    }
+
+    /// Publish `RepositoryEvent::External` when another connection, in this process or
+    /// another one, changes the database. Changes are polled from a background thread that
+    /// stops when the repository is closed or dropped.
+    ///
+    /// The data version covers the whole database, so writes to other tables are reported
+    /// too, and an external write made while this repository writes may be missed.
+    ///
+    /// # Arguments
+    /// * `interval` - How often to poll.
+    pub async fn watch(&self, interval: Duration) -> Result<(), SqliteRepositoryError> {
+        let conn = self.read(Ok).await?;
+        let notifier = Arc::downgrade(&self.notifier);
+        let stop = Arc::new(StopFlag::default());
+        let thread_stop = stop.clone();
+        let thread = thread::Builder::new()
+            .name(format!("sqlite-watch-{}", self.table_name))
+            .spawn(move || watch_data_version(conn, notifier, interval, &thread_stop))?;
+        if let Some(old) = self.watcher.lock().unwrap_or_else(PoisonError::into_inner).replace(Watcher { stop, thread }) {
+            old.stop.stop();
+        }
+        Ok(())
+    }
+
+    /// Wait for the running operations to finish, then stop the watcher and close its
+    /// connection. Once this returns, the repository has no connection open and every
+    /// operation fails with `SqliteRepositoryError::Closed`, e.g. to encrypt the
+    /// database file.
+    pub async fn close(&self) -> Result<(), SqliteRepositoryError> {
+        *self.closed.write().await = true;
+        let watcher = self.watcher.lock().unwrap_or_else(PoisonError::into_inner).take();
+        if let Some(watcher) = watcher {
+            watcher.stop.stop();
+            tokio::task::spawn_blocking(move || {
+                if watcher.thread.join().is_err() {
+                    warn!("sqlite watcher thread panicked");
+                }
+            }).await?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SqliteRepository {
+    fn drop(&mut self) {
+        if let Some(watcher) = self.watcher.get_mut().unwrap_or_else(PoisonError::into_inner).take() {
+            watcher.stop.stop();
+        }
+    }
+}
+
+impl Observable<SqliteRepositoryItem> for SqliteRepository {
+    fn subscribe(&self) -> broadcast::Receiver<RepositoryEvent<SqliteRepositoryItem>> {
+        self.notifier.sender.subscribe()
+    }
+}
+
+fn query_items(conn: &Connection, sql: &str, params: impl rusqlite::Params) -> Result<Vec<SqliteRepositoryItem>, SqliteRepositoryError> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map(params, read_item)?;
+    let mut rs = Vec::new();
+    for x in rows {
+        rs.push(x?);
+    }
+    Ok(rs)
+}
+
+impl SqliteRepository {
+    async fn add_with(&self, item: SqliteRepositoryItem, recorder: Option<AuditRecorder>) -> Result<(), SqliteRepositoryError> {
+        let sql = self.sql_add.clone();
+        let sql_get = self.sql_get_by_id.clone();
+        let item = self.prepare(item)?;
+        self.write(move |tx| {
+            let id = item.id.to_string();
+            tx.execute(&sql, (&id, &item.sku, &item.metadata, item.created_at.timestamp_millis(), item.updated_at.timestamp_millis()))
+                .map_err(|e| sku_taken(e, &item.sku))?;
+            let after = tx.query_row(&sql_get, [id], read_item)?;
+            if let Some(recorder) = recorder {
+                recorder.record(tx, after.id, AuditOperation::Add, None, Some(&after))?;
+            }
+            Ok(after)
+        }, |item| vec![RepositoryEvent::Added(item.clone())]).await?;
+        Ok(())
+    }
+
+    async fn update_with(&self, item: SqliteRepositoryItem, recorder: Option<AuditRecorder>) -> Result<(), SqliteRepositoryError> {
+        let sql = self.sql_update.clone();
+        let sql_get = self.sql_get_by_id.clone();
+        let item = self.prepare(item)?;
+        self.write(move |tx| {
+            let id = item.id.to_string();
+            let before = tx.query_row(&sql_get, [&id], read_item)
+                .optional()?
+                .ok_or(SqliteRepositoryError::ItemNotFound(item.id))?;
+            tx.execute(&sql, (&id, &item.sku, &item.metadata, Utc::now().timestamp_millis()))
+                .map_err(|e| sku_taken(e, &item.sku))?;
+            let after = tx.query_row(&sql_get, [&id], read_item)?;
+            if let Some(recorder) = recorder {
+                recorder.record(tx, after.id, AuditOperation::Update, Some(&before), Some(&after))?;
+            }
+            Ok(after)
+        }, |item| vec![RepositoryEvent::Updated(item.clone())]).await?;
+        Ok(())
+    }
+
+    async fn delete_with(&self, id: Uuid, recorder: Option<AuditRecorder>) -> Result<(), SqliteRepositoryError> {
+        let sql = self.sql_delete.clone();
+        let sql_get = self.sql_get_by_id.clone();
+        let sql_get_deleted = self.sql_get_deleted.clone();
+        self.write(move |tx| {
+            let Some(before) = tx.query_row(&sql_get, [id.to_string()], read_item).optional()? else {
+                return Ok(None);
+            };
+            tx.execute(&sql, (id.to_string(), Utc::now().timestamp_millis()))?;
+            if let Some(recorder) = recorder {
+                recorder.record(tx, id, AuditOperation::Delete, Some(&before), None)?;
+            }
+            Ok(Some(tx.query_row(&sql_get_deleted, [id.to_string()], read_item)?))
+        }, |item| item.iter().cloned().map(RepositoryEvent::Deleted).collect()).await?;
+        Ok(())
+    }
+
+    async fn restore_with(&self, id: Uuid, recorder: Option<AuditRecorder>) -> Result<(), SqliteRepositoryError> {
+        let sql = self.sql_restore.clone();
+        let sql_get = self.sql_get_by_id.clone();
+        let sql_get_deleted = self.sql_get_deleted.clone();
+        self.write(move |tx| {
+            let item = tx.query_row(&sql_get_deleted, [id.to_string()], read_item)
+                .optional()?
+                .ok_or(SqliteRepositoryError::ItemNotFound(id))?;
+            tx.execute(&sql, [id.to_string()]).map_err(|e| sku_taken(e, &item.sku))?;
+            let after = tx.query_row(&sql_get, [id.to_string()], read_item)?;
+            if let Some(recorder) = recorder {
+                recorder.record(tx, id, AuditOperation::Restore, None, Some(&after))?;
+            }
+            Ok(after)
+        }, |item| vec![RepositoryEvent::Restored(item.clone())]).await?;
+        Ok(())
+    }
+
+    async fn purge_with(&self, id: Uuid, recorder: Option<AuditRecorder>) -> Result<(), SqliteRepositoryError> {
+        let sql = self.sql_purge.clone();
+        let sql_get = self.sql_get_deleted.clone();
+        self.write(move |tx| {
+            let item = tx.query_row(&sql_get, [id.to_string()], read_item)
+                .optional()?
+                .ok_or(SqliteRepositoryError::ItemNotFound(id))?;
+            tx.execute(&sql, [id.to_string()])?;
+            if let Some(recorder) = recorder {
+                recorder.record(tx, id, AuditOperation::Purge, Some(&item), None)?;
+            }
+            Ok(item)
+        }, |item| vec![RepositoryEvent::Purged(item.clone())]).await?;
+        Ok(())
+    }
+
+    async fn purge_deleted_before_with(&self, before: DateTime<Utc>, recorder: Option<AuditRecorder>) -> Result<usize, SqliteRepositoryError> {
+        let table_name = self.table_name.clone();
+        let items = self.write(move |tx| {
+            let sql = format!("SELECT {} FROM {} WHERE deleted_at < ?1", COLUMNS, table_name);
+            let items = query_items(tx, &sql, [before.timestamp_millis()])?;
+            tx.execute(&format!("DELETE FROM {} WHERE deleted_at < ?1", table_name), [before.timestamp_millis()])?;
+            if let Some(recorder) = recorder {
+                for item in &items {
+                    recorder.record(tx, item.id, AuditOperation::Purge, Some(item), None)?;
+                }
+            }
+            Ok(items)
+        }, |items| items.iter().cloned().map(RepositoryEvent::Purged).collect()).await?;
+        Ok(items.len())
     }
 }
 
@@ -75,113 +557,159 @@ impl Repository<SqliteRepositoryItem> for SqliteRepository {
     type RepositoryError = SqliteRepositoryError;
 
     async fn get_by_id(&self, id: Uuid) -> Result<Option<SqliteRepositoryItem>, Self::RepositoryError> {
-        let db_path = self.db_path.clone();
         let sql = self.sql_get_by_id.clone();
-
-        tokio::task::spawn_blocking(move || {
-            let conn = Connection::open(db_path)?;
-            let mut stmt = conn.prepare(&sql)?;
-            let mut rows = stmt.query_map([id.to_string()], |row| {
-                Ok(SqliteRepositoryItem {
-                    id: Uuid::parse_str(&row.get::<_, String>(0)?).map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))?,
-                    sku: row.get(1)?,
-                    metadata: row.get(2)?,
-                })
-            })?;
-
-            match rows.next() {
-                Some(Ok(item)) => Ok(Some(item)),
-                Some(Err(e)) => Err(e.into()),
-                None => Ok(None),
-            }
-        }).await?
+        self.read(move |conn| {
+            Ok(conn.query_row(&sql, [id.to_string()], read_item).optional()?)
+        }).await
     }
 
     async fn get_by_sku(&self, sku: &<SqliteRepositoryItem as Storable>::Sku) -> Result<Option<SqliteRepositoryItem>, Self::RepositoryError> {
-        let db_path = self.db_path.clone();
         let sql = self.sql_get_by_sku.clone();
-        let sku = sku.clone();
-
-        tokio::task::spawn_blocking(move || {
-            let conn = Connection::open(db_path)?;
-            let mut stmt = conn.prepare(&sql)?;
-
-            let mut rows = stmt.query_map([sku], |row| {
-                Ok(SqliteRepositoryItem {
-                    id: Uuid::parse_str(&row.get::<_, String>(0)?).map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))?,
-                    sku: row.get(1)?,
-                    metadata: row.get(2)?,
-                })
-            })?;
-
-            match rows.next() {
-                Some(Ok(item)) => Ok(Some(item)),
-                Some(Err(e)) => Err(e.into()),
-                None => Ok(None),
-            }
-        }).await?
+        // A SKU that is not a valid product code can only match a row stored before SKUs were validated.
+        let sku = normalize_sku(sku).unwrap_or_else(|_| sku.clone());
+        self.read(move |conn| {
+            Ok(conn.query_row(&sql, [sku], read_item).optional()?)
+        }).await
     }
 
+    /// Add an item with the creation and update times it carries.
     async fn add(&self, item: SqliteRepositoryItem) -> Result<(), Self::RepositoryError> {
-        let db_path = self.db_path.clone();
-        let sql = self.sql_add.clone();
+        self.add_with(item, None).await
+    }
 
-        tokio::task::spawn_blocking(move || {
-            let conn = Connection::open(db_path)?;
-            let mut stmt = conn.prepare(&sql)?;
-            stmt.execute((&item.id.to_string(), &item.sku, &item.metadata))?;
-            Ok(())
-        }).await?
+    /// Replace the SKU and metadata of an item, setting its update time to now. The
+    /// timestamps of `item` are ignored.
+    async fn update(&self, item: SqliteRepositoryItem) -> Result<(), Self::RepositoryError> {
+        self.update_with(item, None).await
     }
 
+    /// Move an item to the trash. It keeps its id and SKU until it is purged.
     async fn delete(&self, id: Uuid) -> Result<(), Self::RepositoryError> {
-        let db_path = self.db_path.clone();
-        let sql = self.sql_delete.clone();
-
-        tokio::task::spawn_blocking(move || {
-            let conn = Connection::open(db_path)?;
-            let mut stmt = conn.prepare(&sql)?;
-            stmt.execute([id.to_string()])?;
-            Ok(())
-        }).await?
+        self.delete_with(id, None).await
     }
 
     async fn list(&self) -> Result<Vec<SqliteRepositoryItem>, Self::RepositoryError> {
-        let db_path = self.db_path.clone();
         let sql = self.sql_list.clone();
+        self.read(move |conn| query_items(&conn, &sql, [])).await
+    }
+}
 
-        tokio::task::spawn_blocking(move || {
-            let conn = Connection::open(db_path)?;
-            let mut stmt = conn.prepare(&sql)?;
-
-            let rows = stmt.query_map([], |row| {
-                Ok(SqliteRepositoryItem {
-                    id: Uuid::parse_str(&row.get::<_, String>(0)?).map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))?,
-                    sku: row.get(1)?,
-                    metadata: row.get(2)?,
-                })
-            })?;
-
-            let mut rs = Vec::new();
-            for x in rows {
-                rs.push(x?);
-            }
-            Ok(rs)
-        }).await?
+#[async_trait]
+impl Trash<SqliteRepositoryItem> for SqliteRepository {
+    async fn list_with_deleted(&self) -> Result<Vec<SqliteRepositoryItem>, Self::RepositoryError> {
+        let sql = self.sql_list_with_deleted.clone();
+        self.read(move |conn| query_items(&conn, &sql, [])).await
+    }
+
+    /// Take an item out of the trash.
+    ///
+    /// # Errors
+    /// * `SqliteRepositoryError::ItemNotFound` if the item is not in the trash.
+    /// * `SqliteRepositoryError::SkuTaken` if another item was added with its SKU meanwhile.
+    ///   The item stays in the trash until that SKU is changed or removed.
+    async fn restore(&self, id: Uuid) -> Result<(), Self::RepositoryError> {
+        self.restore_with(id, None).await
+    }
+
+    async fn purge(&self, id: Uuid) -> Result<(), Self::RepositoryError> {
+        self.purge_with(id, None).await
+    }
+
+    async fn purge_deleted_before(&self, before: DateTime<Utc>) -> Result<usize, Self::RepositoryError> {
+        self.purge_deleted_before_with(before, None).await
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[async_trait]
+impl RecordedRepository<SqliteRepositoryItem> for SqliteRepository {
+    async fn add_recorded(&self, item: SqliteRepositoryItem, recorder: &AuditRecorder) -> Result<(), Self::RepositoryError> {
+        self.add_with(item, Some(recorder.clone())).await
+    }
+
+    async fn update_recorded(&self, item: SqliteRepositoryItem, recorder: &AuditRecorder) -> Result<(), Self::RepositoryError> {
+        self.update_with(item, Some(recorder.clone())).await
+    }
+
+    async fn delete_recorded(&self, id: Uuid, recorder: &AuditRecorder) -> Result<(), Self::RepositoryError> {
+        self.delete_with(id, Some(recorder.clone())).await
+    }
+
+    async fn restore_recorded(&self, id: Uuid, recorder: &AuditRecorder) -> Result<(), Self::RepositoryError> {
+        self.restore_with(id, Some(recorder.clone())).await
+    }
+
+    async fn purge_recorded(&self, id: Uuid, recorder: &AuditRecorder) -> Result<(), Self::RepositoryError> {
+        self.purge_with(id, Some(recorder.clone())).await
+    }
+
+    async fn purge_deleted_before_recorded(&self, before: DateTime<Utc>, recorder: &AuditRecorder) -> Result<usize, Self::RepositoryError> {
+        self.purge_deleted_before_with(before, Some(recorder.clone())).await
+    }
+}
+
+#[async_trait]
+impl TimeRange<SqliteRepositoryItem> for SqliteRepository {
+    async fn list_between(
+        &self,
+        timestamp: ItemTimestamp,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<SqliteRepositoryItem>, Self::RepositoryError> {
+        let sql = match timestamp {
+            ItemTimestamp::Created => self.sql_list_created_between.clone(),
+            ItemTimestamp::Updated => self.sql_list_updated_between.clone(),
+        };
+        self.read(move |conn| query_items(&conn, &sql, [from.timestamp_millis(), to.timestamp_millis()])).await
+    }
+}
+
+fn read_timestamp(row: &rusqlite::Row<'_>, idx: usize) -> rusqlite::Result<DateTime<Utc>> {
+    let millis = row.get::<_, i64>(idx)?;
+    DateTime::from_timestamp_millis(millis).ok_or(rusqlite::Error::IntegralValueOutOfRange(idx, millis))
+}
+
+fn read_item(row: &rusqlite::Row<'_>) -> rusqlite::Result<SqliteRepositoryItem> {
+    Ok(SqliteRepositoryItem {
+        id: Uuid::parse_str(&row.get::<_, String>(0)?).map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))?,
+        sku: row.get(1)?,
+        metadata: row.get(2)?,
+        deleted_at: row.get::<_, Option<i64>>(3)?.and_then(DateTime::from_timestamp_millis),
+        created_at: read_timestamp(row, 4)?,
+        updated_at: read_timestamp(row, 5)?,
+    })
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SqliteRepositoryItem {
     id: Uuid,
     sku: String,
     metadata: String,
+    #[serde(default)]
+    deleted_at: Option<DateTime<Utc>>,
+    /// Snapshots recorded before the times were kept have none; re-adding one starts
+    /// its history now rather than in 1970.
+    #[serde(default = "Utc::now")]
+    created_at: DateTime<Utc>,
+    #[serde(default = "Utc::now")]
+    updated_at: DateTime<Utc>,
 }
 
 impl SqliteRepositoryItem {
     pub fn new(sku: String, metadata: String) -> Self {
         let id = Uuid::new_v4();
-        Self { id, sku, metadata }
+        let now = Utc::now();
+        Self { id, sku, metadata, deleted_at: None, created_at: now, updated_at: now }
+    }
+
+    /// An existing item with new values, to pass to `update`.
+    pub fn with_id(id: Uuid, sku: String, metadata: String) -> Self {
+        let now = Utc::now();
+        Self { id, sku, metadata, deleted_at: None, created_at: now, updated_at: now }
+    }
+
+    /// When the item was moved to the trash, `None` if it is not in the trash.
+    pub fn deleted_at(&self) -> Option<DateTime<Utc>> {
+        self.deleted_at
     }
 }
 
@@ -200,4 +728,171 @@ impl Storable for SqliteRepositoryItem {
     fn metadata(&self) -> &Self::Metadata {
         &self.metadata
     }
+
+    fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_db(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tools_core-sqlite-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.sqlite", name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn test_normalize_legacy_skus() {
+    let path = test_db("normalize");
+    let conn = Connection::open(&path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE items (id TEXT PRIMARY KEY, sku TEXT NOT NULL UNIQUE, metadata TEXT NOT NULL);
+        INSERT INTO items VALUES ('3c2b1a09-8f7e-4d6c-9b5a-4e3d2c1b0a98', '036000291452', '{}');
+        INSERT INTO items VALUES ('8e7d6c5b-4a39-4281-9f0e-1d2c3b4a5968', '4901234567890', '{}');
+        INSERT INTO items VALUES ('6f1c1f7e-8a4b-4e6c-9d59-1c2b3a4d5e6f', '0042', '{}');",
+    ).unwrap();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let repo = SqliteRepository::new(path.to_str().unwrap(), "items".to_string()).await;
+        repo.create_table().await.unwrap();
+        let item = repo.get_by_sku(&"036000291452".to_string()).await.unwrap().unwrap();
+        assert_eq!(item.sku(), "0036000291452");
+        // An invalid product code stored before validation is still found as it is.
+        assert!(repo.get_by_sku(&"4901234567890".to_string()).await.unwrap().is_some());
+        assert!(repo.get_by_sku(&"0042".to_string()).await.unwrap().is_some());
+        assert!(repo.get_by_sku(&"12345".to_string()).await.unwrap().is_none());
+        assert!(repo.get_by_sku(&"4901234567891".to_string()).await.unwrap().is_none());
+        repo.add(SqliteRepositoryItem::new("2025-08-09".to_string(), "{}".to_string())).await.unwrap();
+        assert!(matches!(
+            repo.add(SqliteRepositoryItem::new("0036000291452".to_string(), "{}".to_string())).await,
+            Err(SqliteRepositoryError::SkuTaken(_))
+        ));
+
+        conn.execute("INSERT INTO items (id, sku, metadata, created_at, updated_at) VALUES ('0b9a7c55-2f0e-4c4b-8f8a-3d5a0c8e9f10', ' 0042', '{}', 0, 0)", []).unwrap();
+        assert!(matches!(repo.create_table().await, Err(SqliteRepositoryError::SkuConflict(..))));
+    });
+    drop(conn);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_trash() {
+    let path = test_db("trash");
+    // A table from before the trash, where SKUs were unique among all rows.
+    let conn = Connection::open(&path).unwrap();
+    conn.execute("CREATE TABLE items (id TEXT PRIMARY KEY, sku TEXT NOT NULL UNIQUE, metadata TEXT NOT NULL)", []).unwrap();
+    conn.execute("INSERT INTO items VALUES ('6f1c1f7e-8a4b-4e6c-9d59-1c2b3a4d5e6f', 'FUMO-0001', '{}')", []).unwrap();
+    // A collection named like a temporary table is left alone by the migration.
+    conn.execute("CREATE TABLE items_migrating (id TEXT PRIMARY KEY, sku TEXT NOT NULL, metadata TEXT NOT NULL)", []).unwrap();
+    conn.execute("INSERT INTO items_migrating VALUES ('8e7d6c5b-4a39-4281-9f0e-1d2c3b4a5968', 'FUMO-0002', '{}')", []).unwrap();
+    let a = Uuid::parse_str("6f1c1f7e-8a4b-4e6c-9d59-1c2b3a4d5e6f").unwrap();
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let repo = SqliteRepository::new(path.to_str().unwrap(), "items".to_string()).await;
+        repo.create_table().await.unwrap();
+        let rowid: i64 = conn.query_row("SELECT rowid FROM items WHERE id = ?1", [a.to_string()], |row| row.get(0)).unwrap();
+        assert_eq!(rowid, 1);
+        let other: String = conn.query_row("SELECT sku FROM items_migrating", [], |row| row.get(0)).unwrap();
+        assert_eq!(other, "FUMO-0002");
+        let sku = "FUMO-0001".to_string();
+
+        repo.delete(a).await.unwrap();
+        assert!(repo.get_by_id(a).await.unwrap().is_none());
+        assert!(repo.get_by_sku(&sku).await.unwrap().is_none());
+        assert!(repo.list().await.unwrap().is_empty());
+        let trashed = repo.list_with_deleted().await.unwrap();
+        assert_eq!(trashed.len(), 1);
+        assert!(trashed[0].deleted_at().is_some());
+
+        // The SKU of an item in the trash can be reused, and then keeps it from being restored.
+        let b = SqliteRepositoryItem::new(sku.clone(), "{}".to_string());
+        repo.add(b.clone()).await.unwrap();
+        assert!(matches!(repo.restore(a).await, Err(SqliteRepositoryError::SkuTaken(x)) if x == sku));
+        assert!(repo.get_by_id(a).await.unwrap().is_none());
+        assert!(matches!(repo.purge(b.id()).await, Err(SqliteRepositoryError::ItemNotFound(_))));
+
+        repo.delete(b.id()).await.unwrap();
+        repo.restore(a).await.unwrap();
+        assert_eq!(repo.get_by_sku(&sku).await.unwrap().unwrap().id(), a);
+        assert!(matches!(repo.restore(a).await, Err(SqliteRepositoryError::ItemNotFound(_))));
+
+        repo.purge(b.id()).await.unwrap();
+        assert_eq!(repo.list_with_deleted().await.unwrap().len(), 1);
+
+        repo.delete(a).await.unwrap();
+        assert_eq!(repo.purge_deleted_before(Utc::now() - chrono::TimeDelta::hours(1)).await.unwrap(), 0);
+        assert_eq!(repo.purge_deleted_before(Utc::now() + chrono::TimeDelta::seconds(1)).await.unwrap(), 1);
+        assert!(repo.list_with_deleted().await.unwrap().is_empty());
+    });
+    drop(conn);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_legacy_timestamps() {
+    let path = test_db("timestamps");
+    // A table from before the creation and update times were recorded.
+    let conn = Connection::open(&path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE items (id TEXT PRIMARY KEY, sku TEXT NOT NULL UNIQUE, metadata TEXT NOT NULL);
+        INSERT INTO items VALUES ('c3b2a190-8f7e-4d6c-9b5a-4e3d2c1b0a98', 'FUMO-0001', '{}');
+        INSERT INTO items VALUES ('a1b2c3d4-4a39-4281-9f0e-1d2c3b4a5968', 'FUMO-0002', '{}');
+        INSERT INTO items VALUES ('b2c3d4e5-8a4b-4e6c-9d59-1c2b3a4d5e6f', 'FUMO-0003', '{}');",
+    ).unwrap();
+    drop(conn);
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let repo = SqliteRepository::new(path.to_str().unwrap(), "items".to_string()).await;
+        let migrated_from = Utc::now();
+        repo.create_table().await.unwrap();
+        let migrated_to = Utc::now() + chrono::Duration::milliseconds(1);
+        let skus = |items: Vec<SqliteRepositoryItem>| items.into_iter().map(|x| x.sku).collect::<Vec<_>>();
+        let legacy = ["FUMO-0001", "FUMO-0002", "FUMO-0003"];
+        assert_eq!(skus(repo.list().await.unwrap()), legacy);
+        let created = repo.list_between(ItemTimestamp::Created, migrated_from, migrated_to).await.unwrap();
+        assert!(created.iter().all(|x| x.created_at() == x.updated_at()));
+        assert_eq!(skus(created), legacy);
+
+        // A snapshot recorded without the times is added as new.
+        thread::sleep(Duration::from_millis(5));
+        let snapshot: SqliteRepositoryItem = serde_json::from_str(
+            r#"{"id":"d4e5f6a7-1b2c-4d3e-8f4a-5b6c7d8e9f01","sku":"FUMO-0004","metadata":"{}"}"#,
+        ).unwrap();
+        repo.add(snapshot).await.unwrap();
+        let later = repo.list_between(ItemTimestamp::Created, migrated_to, Utc::now() + chrono::Duration::seconds(1)).await.unwrap();
+        assert_eq!(skus(later), ["FUMO-0004"]);
+        assert_eq!(skus(repo.list().await.unwrap()).last().unwrap(), "FUMO-0004");
+    });
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_close() {
+    let path = test_db("close");
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async {
+        let repo = SqliteRepository::new(path.to_str().unwrap(), "items".to_string()).await;
+        repo.create_table().await.unwrap();
+        // A long interval, the watcher must not be waited for.
+        repo.watch(Duration::from_secs(3600)).await.unwrap();
+        let started = std::time::Instant::now();
+        repo.close().await.unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(repo.watcher.lock().unwrap().is_none());
+        assert!(matches!(repo.list().await, Err(SqliteRepositoryError::Closed)));
+        assert!(matches!(
+            repo.add(SqliteRepositoryItem::new("FUMO-0001".to_string(), "{}".to_string())).await,
+            Err(SqliteRepositoryError::Closed)
+        ));
+    });
+    fs::remove_file(&path).unwrap();
 }
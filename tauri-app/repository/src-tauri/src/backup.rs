@@ -0,0 +1,65 @@
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use tauri::State;
+use tools_core::infra::backup::BackupInfo;
+use tools_core::infra::repository::encryption;
+
+use crate::config::ConfigState;
+
+/// Back up the database and remove the backups the retention policy does not keep.
+async fn create(config: &ConfigState) -> Result<BackupInfo, String> {
+    let backup = config.backup();
+    let rs = backup.create().await.map_err(|e| e.to_string())?;
+    backup.prune(&config.get().backup_retention).await.map_err(|e| e.to_string())?;
+    Ok(rs)
+}
+
+#[tauri::command]
+pub async fn backup_create(config: State<'_, ConfigState>) -> Result<BackupInfo, String> {
+    create(&config).await
+}
+
+/// The backups of the configured database, newest first.
+#[tauri::command]
+pub async fn backup_list(config: State<'_, ConfigState>) -> Result<Vec<BackupInfo>, String> {
+    config.backup().list().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn backup_verify(path: String, config: State<'_, ConfigState>) -> Result<(), String> {
+    config.backup().verify(&PathBuf::from(path)).await.map_err(|e| e.to_string())
+}
+
+/// Replace the database with a backup. The database is backed up first, so the restore
+/// can be undone by restoring that backup, which is returned.
+#[tauri::command]
+pub async fn backup_restore(path: String, config: State<'_, ConfigState>) -> Result<BackupInfo, String> {
+    let backup = config.backup();
+    let path = PathBuf::from(path);
+    backup.verify(&path).await.map_err(|e| e.to_string())?;
+    let rs = backup.create().await.map_err(|e| e.to_string())?;
+    backup.restore(&path).await.map_err(|e| e.to_string())?;
+    Ok(rs)
+}
+
+/// Back up the database when the app exits, unless it has not changed since the last
+/// backup or it is encrypted and was never unlocked.
+pub async fn backup_on_exit(config: &ConfigState) {
+    let db_path = config.get().db_path;
+    let Ok(modified) = fs::metadata(&db_path).and_then(|x| x.modified()) else {
+        return;
+    };
+    if config.passphrase().is_none() && encryption::is_encrypted(&db_path).unwrap_or(true) {
+        return;
+    }
+    let last = config.backup().list().await.ok().and_then(|x| x.into_iter().next());
+    if last.is_some_and(|x| x.created_at >= DateTime::<Utc>::from(modified)) {
+        return;
+    }
+    if let Err(e) = create(config).await {
+        warn!("Failed to back up the database on exit: {}", e);
+    }
+}
@@ -0,0 +1,34 @@
+use tauri::ipc::{InvokeBody, Request};
+use tauri::State;
+use tools_core::infra::catalog::{Catalog, CatalogEntry, CatalogFormat, CatalogMatch, ImportReport};
+
+use crate::config::ConfigState;
+
+/// Import a catalog file sent as the raw request body, with `csv` or `json` in the
+/// `x-catalog-format` header.
+#[tauri::command]
+pub async fn catalog_import(request: Request<'_>, config: State<'_, ConfigState>) -> Result<ImportReport, String> {
+    let InvokeBody::Raw(data) = request.body() else {
+        return Err("expected the catalog file as the request body".to_string());
+    };
+    let format = request.headers().get("x-catalog-format")
+        .ok_or("missing x-catalog-format header")?
+        .to_str()
+        .map_err(|e| e.to_string())?
+        .parse::<CatalogFormat>()
+        .map_err(|e| e.to_string())?;
+    let catalog = Catalog::open(&config.catalog_path().to_string_lossy()).await.map_err(|e| e.to_string())?;
+    catalog.import(data.clone(), format).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn catalog_lookup(jan: String, config: State<'_, ConfigState>) -> Result<Option<CatalogEntry>, String> {
+    let catalog = Catalog::open(&config.catalog_path().to_string_lossy()).await.map_err(|e| e.to_string())?;
+    catalog.lookup(&jan).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn catalog_search(query: String, limit: Option<usize>, config: State<'_, ConfigState>) -> Result<Vec<CatalogMatch>, String> {
+    let catalog = Catalog::open(&config.catalog_path().to_string_lossy()).await.map_err(|e| e.to_string())?;
+    catalog.search(&query, limit.unwrap_or(10)).await.map_err(|e| e.to_string())
+}
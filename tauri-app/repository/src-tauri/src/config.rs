@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+use tools_core::domain::repository::{ItemRegisterSource, Trash};
+use tools_core::domain::schema::MetadataSchema;
+use tools_core::infra::audit::{AuditContext, Audited, SqliteAuditLog};
+use tools_core::infra::backup::{RetentionPolicy, SqliteBackup};
+use tools_core::infra::repository::integrity::SqliteIntegrity;
+use tools_core::infra::repository::sqlite::{is_valid_table_name, SqliteRepository as Repo};
+
+use crate::repo::forward_changes;
+
+const CONFIG_FILE: &str = "config.json";
+/// How often to check the database for changes made by other processes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+/// Where the database was before it was configurable, relative to the working directory.
+const LEGACY_DB: &str = "./db/fumo.sqlite";
+
+/// A collection of items, stored as a table of the database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Collection {
+    /// The table name, which never changes.
+    pub id: String,
+    /// The name shown in the app.
+    pub name: String,
+    /// The fields expected in the metadata, free-form if not set.
+    #[serde(default)]
+    pub schema: Option<MetadataSchema>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppConfig {
+    pub db_path: PathBuf,
+    pub collections: Vec<Collection>,
+    /// The id of the collection last opened.
+    pub current: Option<String>,
+    /// Items in the trash longer than this are purged when their collection is opened,
+    /// never if not set.
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: Option<u32>,
+    /// Who is recorded in the audit log as making the changes.
+    #[serde(default = "default_operator")]
+    pub operator: String,
+    /// Where backups of the database are kept, `backups` in the app data directory if
+    /// not set.
+    #[serde(default)]
+    pub backup_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub backup_retention: RetentionPolicy,
+}
+
+fn default_trash_retention_days() -> Option<u32> {
+    Some(30)
+}
+
+/// The OS user name, as a starting point until the operator is set.
+fn default_operator() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+impl AppConfig {
+    /// The first configuration, keeping the legacy database and collection if they exist.
+    fn new(data_dir: &Path) -> Self {
+        let legacy = Path::new(LEGACY_DB);
+        let db_path = match legacy.canonicalize() {
+            Ok(path) if legacy.is_file() => path,
+            _ => data_dir.join("db").join("repository.sqlite"),
+        };
+        Self {
+            db_path,
+            collections: vec![Collection {
+                id: "szbdc20250809".to_string(),
+                name: "Fumo Repository".to_string(),
+                schema: None,
+            }],
+            current: None,
+            trash_retention_days: default_trash_retention_days(),
+            operator: default_operator(),
+            backup_dir: None,
+            backup_retention: RetentionPolicy::default(),
+        }
+    }
+
+    pub fn collection(&self, id: &str) -> Result<&Collection, String> {
+        self.collections.iter()
+            .find(|x| x.id == id)
+            .ok_or_else(|| format!("collection `{}` not found", id))
+    }
+}
+
+/// The app configuration, persisted as JSON in the app data directory, and the
+/// repositories opened for its collections.
+pub struct ConfigState {
+    app: AppHandle,
+    data_dir: PathBuf,
+    config: Mutex<AppConfig>,
+    repos: Mutex<HashMap<String, Arc<Repo>>>,
+    /// Unlocks the database if it is encrypted. Only kept in memory.
+    passphrase: Mutex<Option<String>>,
+    /// Set while the database file is encrypted or rekeyed, when no repository may be
+    /// opened.
+    closed: Mutex<bool>,
+}
+
+impl ConfigState {
+    /// Load the configuration from the app data directory, or create the default one.
+    pub fn load(app: AppHandle, data_dir: PathBuf) -> Result<Self, String> {
+        let path = data_dir.join(CONFIG_FILE);
+        let config = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| format!("invalid config file {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => AppConfig::new(&data_dir),
+            Err(e) => return Err(format!("failed to read config file {}: {}", path.display(), e)),
+        };
+        Ok(Self { app, data_dir, config: Mutex::new(config), repos: Mutex::default(), passphrase: Mutex::default(), closed: Mutex::default() })
+    }
+
+    pub fn get(&self) -> AppConfig {
+        self.config.lock().unwrap().clone()
+    }
+
+    pub fn passphrase(&self) -> Option<String> {
+        self.passphrase.lock().unwrap().clone()
+    }
+
+    /// Use another passphrase for the database, reopening the repositories with it.
+    pub fn set_passphrase(&self, passphrase: Option<String>) {
+        *self.passphrase.lock().unwrap() = passphrase;
+        self.repos.lock().unwrap().clear();
+    }
+
+    /// Close the repositories, waiting for their running operations and watchers, and
+    /// refuse to open new ones until `reopen`. Nothing has the database open afterwards
+    /// but other processes and the commands not going through a repository.
+    pub async fn close_repos(&self) -> Result<(), String> {
+        let repos: Vec<_> = {
+            let mut repos = self.repos.lock().unwrap();
+            *self.closed.lock().unwrap() = true;
+            repos.drain().map(|(_, x)| x).collect()
+        };
+        for repo in repos {
+            repo.close().await.map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Allow repositories to be opened again after `close_repos`.
+    pub fn reopen(&self) {
+        *self.closed.lock().unwrap() = false;
+    }
+
+    /// A repository of the configured database, unlocked with the passphrase if any.
+    async fn open_repo(&self, db_path: &Path, collection: &str) -> Repo {
+        let repo = Repo::new(&db_path.to_string_lossy(), collection.to_string()).await;
+        match self.passphrase() {
+            Some(passphrase) => repo.with_passphrase(passphrase),
+            None => repo,
+        }
+    }
+
+    fn audit_log(&self, db_path: &Path, collection: &str) -> SqliteAuditLog {
+        let log = SqliteAuditLog::new(&db_path.to_string_lossy(), collection.to_string());
+        match self.passphrase() {
+            Some(passphrase) => log.with_passphrase(passphrase),
+            None => log,
+        }
+    }
+
+    /// The backups of the configured database.
+    pub fn backup(&self) -> SqliteBackup {
+        let config = self.get();
+        let dir = config.backup_dir.unwrap_or_else(|| self.data_dir.join("backups"));
+        let backup = SqliteBackup::new(config.db_path, dir);
+        match self.passphrase() {
+            Some(passphrase) => backup.with_passphrase(passphrase),
+            None => backup,
+        }
+    }
+
+    /// Integrity checks of the configured database.
+    pub fn integrity(&self) -> SqliteIntegrity {
+        let integrity = SqliteIntegrity::new(self.get().db_path);
+        match self.passphrase() {
+            Some(passphrase) => integrity.with_passphrase(passphrase),
+            None => integrity,
+        }
+    }
+
+    /// Where the JAN catalog is stored, next to the configuration.
+    pub fn catalog_path(&self) -> PathBuf {
+        self.data_dir.join("db").join("catalog.sqlite")
+    }
+
+    /// The repository of a collection of the configured database. The first time it is
+    /// used, its tables are created, the expired trash is purged and its changes are
+    /// forwarded to the webview.
+    pub async fn repo(&self, collection: &str) -> Result<Arc<Repo>, String> {
+        if *self.closed.lock().unwrap() {
+            return Err("the database is being encrypted, try again".to_string());
+        }
+        if let Some(repo) = self.repos.lock().unwrap().get(collection) {
+            return Ok(repo.clone());
+        }
+        let config = self.get();
+        let schema = config.collection(collection)?.schema.clone();
+        let mut repo = self.open_repo(&config.db_path, collection).await;
+        if let Some(schema) = schema {
+            repo = repo.with_schema(schema);
+        }
+        repo.create_table().await.map_err(|e| e.to_string())?;
+        let log = self.audit_log(&config.db_path, collection);
+        log.create_table().await.map_err(|e| e.to_string())?;
+        let repo = Arc::new(repo);
+        if let Some(days) = config.trash_retention_days {
+            let context = AuditContext {
+                operator: config.operator.clone(),
+                source: ItemRegisterSource::Other("trash retention".to_string()),
+            };
+            Audited::new(repo.clone(), Arc::new(log), context)
+                .purge_deleted_before(Utc::now() - TimeDelta::days(days.into())).await
+                .map_err(|e| e.to_string())?;
+        }
+        repo.watch(WATCH_INTERVAL).await.map_err(|e| e.to_string())?;
+        forward_changes(self.app.clone(), collection.to_string(), &repo);
+        // The database may have been switched or closed while the table was created.
+        let closed = {
+            let current = self.config.lock().unwrap();
+            let mut repos = self.repos.lock().unwrap();
+            let closed = *self.closed.lock().unwrap();
+            if !closed && current.db_path == config.db_path {
+                repos.insert(collection.to_string(), repo.clone());
+            }
+            closed
+        };
+        if closed {
+            repo.close().await.map_err(|e| e.to_string())?;
+            return Err("the database is being encrypted, try again".to_string());
+        }
+        Ok(repo)
+    }
+
+    /// The repository of a collection, recording its changes in the audit log as made by
+    /// the configured operator.
+    pub async fn audited(&self, collection: &str, source: Option<ItemRegisterSource>) -> Result<Audited<Repo>, String> {
+        let repo = self.repo(collection).await?;
+        let config = self.get();
+        let log = self.audit_log(&config.db_path, collection);
+        let context = AuditContext {
+            operator: config.operator,
+            source: source.unwrap_or(ItemRegisterSource::Manual),
+        };
+        Ok(Audited::new(repo, Arc::new(log), context))
+    }
+
+    /// Apply a change and save the configuration, leaving it unchanged if either fails.
+    fn update(&self, f: impl FnOnce(&mut AppConfig) -> Result<(), String>) -> Result<AppConfig, String> {
+        let mut config = self.config.lock().unwrap();
+        let mut next = config.clone();
+        f(&mut next)?;
+        fs::create_dir_all(&self.data_dir).map_err(|e| e.to_string())?;
+        let json = serde_json::to_vec_pretty(&next).map_err(|e| e.to_string())?;
+        fs::write(self.data_dir.join(CONFIG_FILE), json).map_err(|e| e.to_string())?;
+        if next.db_path != config.db_path {
+            // The other database needs its own passphrase, if any.
+            self.set_passphrase(None);
+        }
+        *config = next.clone();
+        Ok(next)
+    }
+}
+
+#[tauri::command]
+pub fn config_get(config: State<'_, ConfigState>) -> AppConfig {
+    config.get()
+}
+
+/// Use another database file. Collections are kept and their tables created on first use.
+#[tauri::command]
+pub fn config_set_db_path(path: String, config: State<'_, ConfigState>) -> Result<AppConfig, String> {
+    config.update(|x| {
+        x.db_path = PathBuf::from(path);
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub async fn collection_create(id: String, name: String, config: State<'_, ConfigState>) -> Result<AppConfig, String> {
+    if !is_valid_table_name(&id) {
+        return Err(format!("invalid collection id `{}`, use letters, digits and underscores", id));
+    }
+    if config.get().collection(&id).is_ok() {
+        return Err(format!("collection `{}` already exists", id));
+    }
+    let db_path = config.get().db_path;
+    config.open_repo(&db_path, &id).await
+        .create_table().await
+        .map_err(|e| e.to_string())?;
+    config.update(|x| {
+        x.collections.push(Collection { id: id.clone(), name, schema: None });
+        x.current = Some(id);
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn collection_rename(id: String, name: String, config: State<'_, ConfigState>) -> Result<AppConfig, String> {
+    config.update(|x| {
+        x.collections.iter_mut()
+            .find(|x| x.id == id)
+            .ok_or_else(|| format!("collection `{}` not found", id))?
+            .name = name;
+        Ok(())
+    })
+}
+
+#[tauri::command]
+pub fn collection_switch(id: String, config: State<'_, ConfigState>) -> Result<AppConfig, String> {
+    config.update(|x| {
+        x.collection(&id)?;
+        x.current = Some(id);
+        Ok(())
+    })
+}
+
+/// Set or clear the metadata schema of a collection. Existing items are not checked, the
+/// schema applies to the next added or updated ones.
+#[tauri::command]
+pub async fn collection_set_schema(
+    id: String,
+    schema: Option<MetadataSchema>,
+    config: State<'_, ConfigState>,
+) -> Result<AppConfig, String> {
+    let rs = config.update(|x| {
+        x.collections.iter_mut()
+            .find(|x| x.id == id)
+            .ok_or_else(|| format!("collection `{}` not found", id))?
+            .schema = schema;
+        Ok(())
+    })?;
+    // Reopen the repository so it validates with the new schema.
+    config.repos.lock().unwrap().remove(&id);
+    config.repo(&id).await?;
+    Ok(rs)
+}
+
+/// Set how many days items stay in the trash, `None` to keep them until purged by hand.
+#[tauri::command]
+pub fn config_set_trash_retention(days: Option<u32>, config: State<'_, ConfigState>) -> Result<AppConfig, String> {
+    config.update(|x| {
+        x.trash_retention_days = days;
+        Ok(())
+    })
+}
+
+/// Set who is recorded in the audit log as making the changes.
+#[tauri::command]
+pub fn config_set_operator(operator: String, config: State<'_, ConfigState>) -> Result<AppConfig, String> {
+    if operator.trim().is_empty() {
+        return Err("operator must not be empty".to_string());
+    }
+    config.update(|x| {
+        x.operator = operator.trim().to_string();
+        Ok(())
+    })
+}
+
+/// Set where backups are kept, the app data directory if `None`, and how many.
+#[tauri::command]
+pub fn config_set_backup(
+    dir: Option<String>,
+    retention: RetentionPolicy,
+    config: State<'_, ConfigState>,
+) -> Result<AppConfig, String> {
+    config.update(|x| {
+        x.backup_dir = dir.filter(|x| !x.is_empty()).map(PathBuf::from);
+        x.backup_retention = retention;
+        Ok(())
+    })
+}
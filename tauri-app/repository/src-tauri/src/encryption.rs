@@ -0,0 +1,69 @@
+use serde::Serialize;
+use tauri::State;
+use tools_core::infra::repository::encryption;
+
+use crate::config::ConfigState;
+
+#[derive(Clone, Serialize)]
+pub struct DbStatus {
+    /// Whether databases can be encrypted, only in builds with the `sqlcipher` feature.
+    pub supported: bool,
+    pub encrypted: bool,
+    /// Whether the collections can be opened, always if the database is not encrypted.
+    pub unlocked: bool,
+}
+
+#[tauri::command]
+pub fn db_status(config: State<'_, ConfigState>) -> Result<DbStatus, String> {
+    let encrypted = encryption::is_encrypted(config.get().db_path).map_err(|e| e.to_string())?;
+    Ok(DbStatus {
+        supported: encryption::is_supported(),
+        encrypted,
+        unlocked: !encrypted || config.passphrase().is_some(),
+    })
+}
+
+/// Check the passphrase of the encrypted database and keep it to open the collections
+/// until the app is closed.
+#[tauri::command]
+pub async fn db_unlock(passphrase: String, config: State<'_, ConfigState>) -> Result<(), String> {
+    let db_path = config.get().db_path;
+    let key = passphrase.clone();
+    tauri::async_runtime::spawn_blocking(move || encryption::open(db_path, Some(&key)).map(|_| ()))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+    config.set_passphrase(Some(passphrase));
+    Ok(())
+}
+
+/// Encrypt the plaintext database with a passphrase, then its backups so no readable copy
+/// is left. There is no way back.
+#[tauri::command]
+pub async fn db_encrypt(passphrase: String, config: State<'_, ConfigState>) -> Result<(), String> {
+    let db_path = config.get().db_path;
+    // Nothing may write to the file being replaced, or keep it open on Windows.
+    config.close_repos().await?;
+    let rs = encryption::encrypt(&db_path.to_string_lossy(), &passphrase).await;
+    config.reopen();
+    rs.map_err(|e| e.to_string())?;
+    config.set_passphrase(Some(passphrase.clone()));
+    config.backup().encrypt_plaintext(&passphrase).await
+        .map_err(|e| format!("The database is encrypted, but some of its backups are not: {}", e))?;
+    Ok(())
+}
+
+/// Change the passphrase of the encrypted database, then of its backups so the old
+/// passphrase no longer opens them.
+#[tauri::command]
+pub async fn db_rekey(old: String, new: String, config: State<'_, ConfigState>) -> Result<(), String> {
+    let db_path = config.get().db_path;
+    config.close_repos().await?;
+    let rs = encryption::rekey(&db_path.to_string_lossy(), &old, &new).await;
+    config.reopen();
+    rs.map_err(|e| e.to_string())?;
+    config.set_passphrase(Some(new.clone()));
+    config.backup().rekey_encrypted(&old, &new).await
+        .map_err(|e| format!("The passphrase is changed, but not for some of the backups: {}", e))?;
+    Ok(())
+}
@@ -0,0 +1,17 @@
+use tauri::State;
+use tools_core::infra::repository::integrity::{IntegrityReport, Repair};
+
+use crate::config::ConfigState;
+
+/// Check the configured database for damage and for rows the repositories cannot read or
+/// find.
+#[tauri::command]
+pub async fn db_check(config: State<'_, ConfigState>) -> Result<IntegrityReport, String> {
+    config.integrity().check().await.map_err(|e| e.to_string())
+}
+
+/// Fix a row of an issue found by `db_check`.
+#[tauri::command]
+pub async fn db_repair(table: String, rowid: i64, repair: Repair, config: State<'_, ConfigState>) -> Result<(), String> {
+    config.integrity().repair(&table, rowid, repair).await.map_err(|e| e.to_string())
+}
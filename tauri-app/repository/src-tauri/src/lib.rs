@@ -1,22 +1,218 @@
-mod fumo;
+mod backup;
+mod catalog;
+mod config;
+mod encryption;
+mod integrity;
+mod repo;
 
+use std::sync::Mutex;
+use std::time::Instant;
+
+use tauri::ipc::{InvokeBody, Request, Response};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tools_core::infra::barcode::{decode_all_from_image, decode_all_from_image_file, DecodedBarcode};
+use tools_core::infra::barcode::convert::{Frame, PixelFormat};
+use tools_core::infra::barcode::options::ScanOptions;
+use tools_core::infra::barcode::session::{ScanSession, ScanStats, SessionOptions};
+use tools_core::infra::barcode::worker::ScanWorker;
+use tools_core::infra::barcode::encode::{encode_image, EncodeOptions, ImageFormat, Symbology};
+use tools_core::infra::label::LabelLayout;
+
+/// Read a camera frame sent as the raw request body, with its size in the
+/// `x-frame-width` / `x-frame-height` headers and its pixel layout in `x-frame-format`
+/// (`luma`, `rgba` or `nv12`, default `luma`).
+fn frame_from_request(request: &Request<'_>) -> Result<Frame, String> {
+    let InvokeBody::Raw(data) = request.body() else {
+        return Err("expected the frame as the request body".to_string());
+    };
+    let header = |name: &str| request.headers().get(name)
+        .map(|x| x.to_str().map_err(|e| format!("invalid {} header: {}", name, e)))
+        .transpose();
+    let dimension = |name: &str| header(name)?
+        .ok_or_else(|| format!("missing {} header", name))?
+        .parse::<u32>()
+        .map_err(|e| format!("invalid {} header: {}", name, e));
+    let format = match header("x-frame-format")? {
+        Some(x) => x.parse::<PixelFormat>().map_err(|e| e.to_string())?,
+        None => PixelFormat::Luma,
+    };
+    Ok(Frame {
+        format,
+        data: data.clone(),
+        width: dimension("x-frame-width")?,
+        height: dimension("x-frame-height")?,
+    })
+}
+
+/// Read the scan options sent as JSON in the optional `x-scan-options` header.
+fn scan_options_from_request(request: &Request<'_>) -> Result<ScanOptions, String> {
+    match request.headers().get("x-scan-options") {
+        Some(x) => {
+            let json = x.to_str().map_err(|e| format!("invalid x-scan-options header: {}", e))?;
+            serde_json::from_str(json).map_err(|e| format!("invalid x-scan-options header: {}", e))
+        }
+        None => Ok(ScanOptions::default()),
+    }
+}
+
+/// Decode the first barcode in a camera frame, `None` if there is none or the frame was
+/// dropped for a newer one.
 #[tauri::command]
-async fn scan_barcode(luma: Vec<u8>, width: u32, height: u32) -> Result<Option<String>, String> {
-    use tools_core::infra::barcode::decode_text_from_luma;
+async fn scan_barcode(request: Request<'_>, worker: State<'_, ScanWorker>) -> Result<Option<String>, String> {
+    let rs = worker.decode(frame_from_request(&request)?, scan_options_from_request(&request)?).await
+        .map_err(|e| e.to_string())?;
+    Ok(rs.and_then(|x| x.into_iter().next()).map(|x| x.text))
+}
 
-    let rs = decode_text_from_luma(luma, width, height).await
+/// Decode every barcode in a camera frame, empty if the frame was dropped for a newer one.
+#[tauri::command]
+async fn scan_barcodes(request: Request<'_>, worker: State<'_, ScanWorker>) -> Result<Vec<DecodedBarcode>, String> {
+    let rs = worker.decode(frame_from_request(&request)?, scan_options_from_request(&request)?).await
+        .map_err(|e| e.to_string())?;
+    Ok(rs.unwrap_or_default())
+}
+
+/// The active continuous scan, if any.
+#[derive(Default)]
+struct ScanSessionState(Mutex<Option<ScanSession>>);
+
+/// The codes confirmed by a frame of a continuous scan.
+#[derive(Clone, serde::Serialize)]
+struct ScanEvent {
+    barcodes: Vec<DecodedBarcode>,
+    width: u32,
+    height: u32,
+}
+
+/// Start a continuous scan, replacing the active one.
+#[tauri::command]
+fn scan_session_start(options: Option<SessionOptions>, state: State<'_, ScanSessionState>) {
+    *state.0.lock().unwrap() = Some(ScanSession::new(options.unwrap_or_default()));
+}
+
+/// Stop the continuous scan, returning its final statistics.
+#[tauri::command]
+fn scan_session_stop(state: State<'_, ScanSessionState>) -> Option<ScanStats> {
+    state.0.lock().unwrap().take().map(|x| x.stats())
+}
+
+/// Decode a camera frame for the continuous scan. Confirmed codes are pushed as a
+/// `barcode-scan` event and the statistics as a `barcode-scan-stats` event.
+#[tauri::command]
+async fn scan_session_frame(
+    app: AppHandle,
+    request: Request<'_>,
+    worker: State<'_, ScanWorker>,
+    state: State<'_, ScanSessionState>,
+) -> Result<(), String> {
+    let frame = frame_from_request(&request)?;
+    let (width, height) = (frame.width, frame.height);
+    let options = match state.0.lock().unwrap().as_ref() {
+        Some(session) => session.options().scan.clone(),
+        None => return Ok(()),
+    };
+    let start = Instant::now();
+    let rs = worker.decode(frame, options).await
+        .map_err(|e| e.to_string())?;
+    let (barcodes, stats) = {
+        let mut session = state.0.lock().unwrap();
+        let Some(session) = session.as_mut() else {
+            // Stopped while the frame was decoding.
+            return Ok(());
+        };
+        let barcodes = match rs {
+            Some(rs) => session.observe(rs, start.elapsed(), Instant::now()),
+            None => {
+                session.drop_frame();
+                vec![]
+            }
+        };
+        (barcodes, session.stats())
+    };
+    if !barcodes.is_empty() {
+        app.emit("barcode-scan", ScanEvent { barcodes, width, height })
+            .map_err(|e| e.to_string())?;
+    }
+    app.emit("barcode-scan-stats", stats)
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Decode an image file sent as the raw request body, e.g. one picked with a file input.
+#[tauri::command]
+async fn scan_image(request: Request<'_>) -> Result<Vec<DecodedBarcode>, String> {
+    let InvokeBody::Raw(bytes) = request.body() else {
+        return Err("expected the image file as the request body".to_string());
+    };
+    let rs = decode_all_from_image(bytes.clone(), scan_options_from_request(&request)?).await
+        .map_err(|e| e.to_string())?;
+    Ok(rs)
+}
+
+/// Decode an image file by path, e.g. one dropped onto the window.
+#[tauri::command]
+async fn scan_image_file(path: String, options: Option<ScanOptions>) -> Result<Vec<DecodedBarcode>, String> {
+    let rs = decode_all_from_image_file(path.into(), options.unwrap_or_default()).await
         .map_err(|e| e.to_string())?;
     Ok(rs)
 }
 
+#[tauri::command]
+async fn generate_barcode(
+    contents: String,
+    symbology: Symbology,
+    options: Option<EncodeOptions>,
+    format: ImageFormat,
+) -> Result<Response, String> {
+    let rs = encode_image(contents, symbology, options.unwrap_or_default(), format).await
+        .map_err(|e| e.to_string())?;
+    Ok(Response::new(rs))
+}
+
+#[derive(serde::Serialize)]
+struct LabelPreset {
+    name: &'static str,
+    layout: LabelLayout,
+}
+
+#[tauri::command]
+fn label_presets() -> Vec<LabelPreset> {
+    LabelLayout::presets()
+        .into_iter()
+        .map(|(name, layout)| LabelPreset { name, layout })
+        .collect()
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .manage(ScanWorker::new())
+        .manage(ScanSessionState::default())
+        .setup(|app| {
+            let config = config::ConfigState::load(app.handle().clone(), app.path().app_data_dir()?)?;
+            app.manage(config);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
-            scan_barcode,
-            fumo::fumo_load, fumo::fumo_get_by_uid, fumo::fumo_get_by_sku, fumo::fumo_add, fumo::fumo_remove
+            scan_barcode, scan_barcodes, scan_image, scan_image_file, generate_barcode, label_presets,
+            scan_session_start, scan_session_stop, scan_session_frame,
+            config::config_get, config::config_set_db_path, config::config_set_trash_retention, config::config_set_operator, config::config_set_backup,
+            config::collection_create, config::collection_rename, config::collection_switch, config::collection_set_schema,
+            backup::backup_create, backup::backup_list, backup::backup_verify, backup::backup_restore,
+            encryption::db_status, encryption::db_unlock, encryption::db_encrypt, encryption::db_rekey,
+            integrity::db_check, integrity::db_repair,
+            catalog::catalog_import, catalog::catalog_lookup, catalog::catalog_search,
+            repo::repo_list, repo::repo_list_between, repo::repo_get, repo::repo_get_by_sku, repo::repo_add, repo::repo_update, repo::repo_validate, repo::repo_remove, repo::repo_labels,
+            repo::repo_trash, repo::repo_restore, repo::repo_purge, repo::repo_empty_trash,
+            repo::repo_history, repo::repo_revert
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                let config = app.state::<config::ConfigState>();
+                tauri::async_runtime::block_on(backup::backup_on_exit(&config));
+            }
+        });
 }
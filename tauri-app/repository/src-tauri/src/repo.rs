@@ -0,0 +1,228 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use tauri::ipc::Response;
+use tauri::{AppHandle, Emitter, State};
+use tokio::sync::broadcast::error::RecvError;
+use tools_core::domain::repository::{
+    ItemRegisterSource, ItemTimestamp, Observable, Repository, RepositoryEvent, Storable, TimeRange, Trash,
+};
+use tools_core::domain::schema::{FieldError, SchemaError};
+use tools_core::infra::repository::sqlite::{SqliteRepository as Repo, SqliteRepositoryItem as SqlItem};
+use tools_core::infra::audit::AuditEntry;
+use tools_core::infra::label::{render_repository, LabelFont, LabelLayout};
+use uuid::Uuid;
+
+use crate::config::ConfigState;
+
+/// An item as sent to the webview. Mirrored by hand by `RowData` in
+/// `src/components/plugin/interface.ts`, like the other payload types.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Item {
+    pub uid: String,
+    pub sku: String,
+    pub metadata: String,
+    /// When the item was moved to the trash, as RFC 3339.
+    pub deleted_at: Option<String>,
+    /// As RFC 3339.
+    pub created_at: String,
+    /// As RFC 3339.
+    pub updated_at: String,
+}
+
+impl From<SqlItem> for Item {
+    fn from(item: SqlItem) -> Self {
+        Item {
+            uid: item.id().to_string(),
+            sku: item.sku().to_string(),
+            metadata: item.metadata().to_string(),
+            deleted_at: item.deleted_at().map(|x| x.to_rfc3339()),
+            created_at: item.created_at().to_rfc3339(),
+            updated_at: item.updated_at().to_rfc3339(),
+        }
+    }
+}
+
+/// A change to a collection, pushed to the webview as a `repo-change` event.
+#[derive(Clone, Serialize)]
+struct RepoChange {
+    collection: String,
+    event: RepositoryEvent<Item>,
+}
+
+/// Forward the changes of a collection to the webview until the repository is dropped.
+pub fn forward_changes(app: AppHandle, collection: String, repo: &Repo) {
+    let mut events = repo.subscribe();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(x) => x.map(Item::from),
+                // Missed changes are unknown, same as external ones.
+                Err(RecvError::Lagged(_)) => RepositoryEvent::External,
+                Err(RecvError::Closed) => break,
+            };
+            let change = RepoChange { collection: collection.clone(), event };
+            // Only fails if the webview is gone, the next change will try again.
+            let _ = app.emit("repo-change", change);
+        }
+    });
+}
+
+fn parse_uid(uid: &str) -> Result<Uuid, String> {
+    Uuid::parse_str(uid).map_err(|e| format!("invalid uid `{}`: {}", uid, e))
+}
+
+#[tauri::command]
+pub async fn repo_list(collection: String, config: State<'_, ConfigState>) -> Result<Vec<Item>, String> {
+    let repo = config.repo(&collection).await?;
+    Ok(repo.list().await.map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|x| x.into())
+        .collect())
+}
+
+/// The items created or updated in `[from, to)`, oldest first.
+#[tauri::command]
+pub async fn repo_list_between(
+    collection: String,
+    timestamp: ItemTimestamp,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    config: State<'_, ConfigState>,
+) -> Result<Vec<Item>, String> {
+    let repo = config.repo(&collection).await?;
+    Ok(repo.list_between(timestamp, from, to).await.map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|x| x.into())
+        .collect())
+}
+
+#[tauri::command]
+pub async fn repo_get(collection: String, uid: String, config: State<'_, ConfigState>) -> Result<Option<Item>, String> {
+    let repo = config.repo(&collection).await?;
+    let rs = repo.get_by_id(parse_uid(&uid)?).await.map_err(|e| e.to_string())?
+        .map(|x| x.into());
+    Ok(rs)
+}
+
+#[tauri::command]
+pub async fn repo_get_by_sku(collection: String, sku: String, config: State<'_, ConfigState>) -> Result<Option<Item>, String> {
+    let repo = config.repo(&collection).await?;
+    let rs = repo.get_by_sku(&sku).await.map_err(|e| e.to_string())?
+        .map(|x| x.into());
+    Ok(rs)
+}
+
+#[tauri::command]
+pub async fn repo_add(
+    collection: String,
+    sku: String,
+    metadata: String,
+    source: Option<ItemRegisterSource>,
+    config: State<'_, ConfigState>,
+) -> Result<(), String> {
+    let repo = config.audited(&collection, source).await?;
+    let item = SqlItem::new(sku, metadata);
+    repo.add(item).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn repo_update(
+    collection: String,
+    uid: String,
+    sku: String,
+    metadata: String,
+    source: Option<ItemRegisterSource>,
+    config: State<'_, ConfigState>,
+) -> Result<(), String> {
+    let repo = config.audited(&collection, source).await?;
+    let item = SqlItem::with_id(parse_uid(&uid)?, sku, metadata);
+    repo.update(item).await.map_err(|e| e.to_string())
+}
+
+/// Check metadata against the schema of a collection, empty if it is valid or the
+/// collection has no schema.
+#[tauri::command]
+pub async fn repo_validate(collection: String, metadata: String, config: State<'_, ConfigState>) -> Result<Vec<FieldError>, String> {
+    let repo = config.repo(&collection).await?;
+    match repo.schema().map(|x| x.validate(&metadata)) {
+        Some(Err(SchemaError::Invalid(errors))) => Ok(errors),
+        _ => Ok(vec![]),
+    }
+}
+
+#[tauri::command]
+pub async fn repo_remove(collection: String, uid: String, config: State<'_, ConfigState>) -> Result<(), String> {
+    let repo = config.audited(&collection, None).await?;
+    repo.delete(parse_uid(&uid)?).await.map_err(|e| e.to_string())
+}
+
+/// The items in the trash of a collection, most recently deleted first.
+#[tauri::command]
+pub async fn repo_trash(collection: String, config: State<'_, ConfigState>) -> Result<Vec<Item>, String> {
+    let repo = config.repo(&collection).await?;
+    let mut items: Vec<_> = repo.list_with_deleted().await.map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|x| x.deleted_at().is_some())
+        .collect();
+    items.sort_by_key(|x| std::cmp::Reverse(x.deleted_at()));
+    Ok(items.into_iter().map(|x| x.into()).collect())
+}
+
+#[tauri::command]
+pub async fn repo_restore(collection: String, uid: String, config: State<'_, ConfigState>) -> Result<(), String> {
+    let repo = config.audited(&collection, None).await?;
+    repo.restore(parse_uid(&uid)?).await.map_err(|e| e.to_string())
+}
+
+/// Remove an item in the trash for good.
+#[tauri::command]
+pub async fn repo_purge(collection: String, uid: String, config: State<'_, ConfigState>) -> Result<(), String> {
+    let repo = config.audited(&collection, None).await?;
+    repo.purge(parse_uid(&uid)?).await.map_err(|e| e.to_string())
+}
+
+/// Remove the items in the trash for good, only the ones deleted more than
+/// `older_than_days` ago if set. Returns how many were removed.
+#[tauri::command]
+pub async fn repo_empty_trash(collection: String, older_than_days: Option<u32>, config: State<'_, ConfigState>) -> Result<usize, String> {
+    let repo = config.audited(&collection, None).await?;
+    let before = Utc::now() - TimeDelta::days(older_than_days.unwrap_or(0).into());
+    repo.purge_deleted_before(before).await.map_err(|e| e.to_string())
+}
+
+/// The recorded changes of an item, oldest first.
+#[tauri::command]
+pub async fn repo_history(collection: String, uid: String, config: State<'_, ConfigState>) -> Result<Vec<AuditEntry<Item>>, String> {
+    let repo = config.audited(&collection, None).await?;
+    let rs = repo.log().history::<SqlItem>(parse_uid(&uid)?).await.map_err(|e| e.to_string())?;
+    Ok(rs.into_iter().map(|x| x.map(Item::from)).collect())
+}
+
+/// Put an item back in the state it had after one of its recorded changes.
+#[tauri::command]
+pub async fn repo_revert(collection: String, uid: String, entry_id: i64, config: State<'_, ConfigState>) -> Result<(), String> {
+    let repo = config.audited(&collection, None).await?;
+    repo.revert::<SqlItem>(parse_uid(&uid)?, entry_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn repo_labels(
+    collection: String,
+    uids: Option<Vec<String>>,
+    layout: LabelLayout,
+    skip: Option<usize>,
+    font_path: Option<String>,
+    config: State<'_, ConfigState>,
+) -> Result<Response, String> {
+    let repo = config.repo(&collection).await?;
+    let uids = uids
+        .map(|x| x.iter().map(|x| parse_uid(x)).collect::<Result<Vec<_>, _>>())
+        .transpose()?;
+    let font = match font_path {
+        Some(path) => LabelFont::open(path),
+        None => LabelFont::system_cjk(),
+    }.map_err(|e| e.to_string())?;
+    let rs = render_repository(repo.as_ref(), uids.as_deref(), layout, font, skip.unwrap_or(0)).await
+        .map_err(|e| e.to_string())?;
+    Ok(Response::new(rs))
+}
@@ -0,0 +1,138 @@
+import { invoke } from "@tauri-apps/api/core";
+import { MetadataSchema } from "./interface";
+
+export interface Collection {
+    id: string;
+    name: string;
+    schema: MetadataSchema | null;
+}
+
+export interface AppConfig {
+    db_path: string;
+    collections: Collection[];
+    current: string | null;
+    /** Who is recorded in the audit log as making the changes. */
+    operator: string;
+    backup_dir: string | null;
+    backup_retention: RetentionPolicy;
+}
+
+export interface RetentionPolicy {
+    /** How many of the newest backups to keep. */
+    keep_last: number;
+    /** Backups older than this are removed even if among the newest. */
+    max_age_days: number | null;
+}
+
+export interface BackupInfo {
+    path: string;
+    /** RFC 3339 */
+    created_at: string;
+    size: number;
+}
+
+export async function getConfig() {
+    return await invoke<AppConfig>("config_get");
+}
+
+export async function setDbPath(path: string) {
+    return await invoke<AppConfig>("config_set_db_path", { path });
+}
+
+export async function createCollection(id: string, name: string) {
+    return await invoke<AppConfig>("collection_create", { id, name });
+}
+
+export async function renameCollection(id: string, name: string) {
+    return await invoke<AppConfig>("collection_rename", { id, name });
+}
+
+export async function switchCollection(id: string) {
+    return await invoke<AppConfig>("collection_switch", { id });
+}
+
+export async function setCollectionSchema(
+    id: string,
+    schema: MetadataSchema | null
+) {
+    return await invoke<AppConfig>("collection_set_schema", { id, schema });
+}
+
+export async function setOperator(operator: string) {
+    return await invoke<AppConfig>("config_set_operator", { operator });
+}
+
+export interface DbStatus {
+    /** Whether the app was built with SQLCipher, to encrypt databases. */
+    supported: boolean;
+    encrypted: boolean;
+    /** Whether the collections can be opened, always if not encrypted. */
+    unlocked: boolean;
+}
+
+export async function getDbStatus() {
+    return await invoke<DbStatus>("db_status");
+}
+
+export async function unlockDb(passphrase: string) {
+    await invoke("db_unlock", { passphrase });
+}
+
+/** Encrypt the plaintext database. There is no way back. */
+export async function encryptDb(passphrase: string) {
+    await invoke("db_encrypt", { passphrase });
+}
+
+export async function rekeyDb(oldPassphrase: string, newPassphrase: string) {
+    await invoke("db_rekey", { old: oldPassphrase, new: newPassphrase });
+}
+
+export async function setBackup(dir: string | null, retention: RetentionPolicy) {
+    return await invoke<AppConfig>("config_set_backup", { dir, retention });
+}
+
+export async function createBackup() {
+    return await invoke<BackupInfo>("backup_create");
+}
+
+export async function listBackups() {
+    return await invoke<BackupInfo[]>("backup_list");
+}
+
+export async function verifyBackup(path: string) {
+    await invoke("backup_verify", { path });
+}
+
+/** Replace the database with a backup, returning the backup taken just before. */
+export async function restoreBackup(path: string) {
+    return await invoke<BackupInfo>("backup_restore", { path });
+}
+
+export type IssueKind =
+    | { kind: "invalid_id"; id: string }
+    | { kind: "duplicate_id"; id: string; first_rowid: number }
+    | { kind: "empty_sku" }
+    | { kind: "invalid_sku"; sku: string; message: string }
+    | { kind: "unnormalized_sku"; sku: string; normalized: string }
+    | { kind: "duplicate_sku"; sku: string; normalized: string; first_rowid: number }
+    | { kind: "invalid_timestamp"; column: string }
+    | { kind: "orphaned_audit_entry"; collection: string; item_id: string };
+
+export type Issue = { table: string; rowid: number } & IssueKind;
+
+export interface IntegrityReport {
+    /** Problems with the database file, empty if it is fine. */
+    storage: string[];
+    tables: string[];
+    issues: Issue[];
+}
+
+export type Repair = "quarantine" | "new_id" | "normalize_sku";
+
+export async function checkDb() {
+    return await invoke<IntegrityReport>("db_check");
+}
+
+export async function repairDb(table: string, rowid: number, repair: Repair) {
+    await invoke("db_repair", { table, rowid, repair });
+}
@@ -1,22 +1,169 @@
 import { DataTableColumn } from "naive-ui";
 
+// The payload types below are kept in sync with the Rust types they are serialized from
+// by hand, each names its type. Change both together.
+
+/**
+ * `Item` in `src-tauri/src/repo.rs`. Plugins not backed by the database may leave out
+ * the times.
+ */
 export interface RowData {
     uid: string;
     sku: string;
-    metadata: any;
+    /** Usually a JSON object, see `format_metadata`. */
+    metadata: string;
+    /** When the row was moved to the trash, as RFC 3339. */
+    deleted_at?: string | null;
+    /** RFC 3339 */
+    created_at?: string;
+    /** RFC 3339 */
+    updated_at?: string;
 }
 
+/** `ItemTimestamp` in `tools_core::domain::repository`. */
+export type ItemTimestamp = "created" | "updated";
+
 export interface RepositoryCallback {
     load: () => RowData[] | Promise<RowData[]>;
+    /** The rows with the timestamp in `[from, to)`. */
+    list_between?: (
+        timestamp: ItemTimestamp,
+        from: Date,
+        to: Date
+    ) => Promise<RowData[]>;
     get_by_uid: (
         uid: string
     ) => RowData | undefined | Promise<RowData | undefined>;
     get_by_sku: (
         sku: string
     ) => RowData | undefined | Promise<RowData | undefined>;
-    add: (sku: string, metadata: any) => void | Promise<void>;
+    /** Rejects with the reason if the item is not stored, e.g. an invalid or taken SKU. */
+    add: (
+        sku: string,
+        metadata: string,
+        source?: ItemSource
+    ) => void | Promise<void>;
     rm: (uid: string) => void | Promise<void>;
-    format_metadata: (metadata: any) => string;
+    format_metadata: (metadata: string) => string;
+    labels?: (uids: string[], options: LabelOptions) => Promise<ArrayBuffer>;
+    update?: (uid: string, sku: string, metadata: string) => Promise<void>;
+    validate?: (metadata: string) => Promise<FieldError[]>;
+    trash?: () => Promise<RowData[]>;
+    restore?: (uid: string) => Promise<void>;
+    purge?: (uid: string) => Promise<void>;
+    empty_trash?: () => Promise<number>;
+    history?: (uid: string) => Promise<AuditEntry[]>;
+    revert?: (uid: string, entryId: number) => Promise<void>;
+}
+
+/**
+ * Where a change came from, as recorded in the audit log. `ItemRegisterSource` in
+ * `tools_core::domain::repository`.
+ */
+export type ItemSource = "manual" | "qr_code" | "api" | { other: string };
+
+/** `AuditEntry<Item>` in `tools_core::infra::audit`. */
+export interface AuditEntry {
+    id: number;
+    item_id: string;
+    operation: "add" | "update" | "delete" | "restore" | "purge";
+    operator: string;
+    source: ItemSource;
+    /** RFC 3339 */
+    at: string;
+    before: RowData | null;
+    after: RowData | null;
+}
+
+/** `FieldKind` in `tools_core::domain::schema`. */
+export type FieldKind =
+    | { type: "text"; max_length?: number | null }
+    | { type: "integer"; min?: number | null; max?: number | null }
+    | {
+          type: "number";
+          min?: number | null;
+          max?: number | null;
+          unit?: string | null;
+      }
+    | { type: "boolean" }
+    | { type: "choice"; options: string[] }
+    | { type: "date" };
+
+/** `FieldSchema` in `tools_core::domain::schema`. */
+export type FieldSchema = FieldKind & {
+    key: string;
+    label?: string | null;
+    required?: boolean;
+};
+
+/** `MetadataSchema` in `tools_core::domain::schema`. */
+export interface MetadataSchema {
+    fields: FieldSchema[];
+    allow_other?: boolean;
+}
+
+/** `FieldError` in `tools_core::domain::schema`. */
+export interface FieldError {
+    /** Empty if the metadata as a whole is invalid. */
+    key: string;
+    message: string;
+}
+
+/**
+ * A change to a collection, pushed by the backend as a `repo-change` event. `RepoChange`
+ * in `src-tauri/src/repo.rs`, with a `RepositoryEvent<Item>`.
+ */
+export interface RepoChange {
+    collection: string;
+    event:
+        | {
+              kind: "added" | "updated" | "deleted" | "restored" | "purged";
+              item: RowData;
+          }
+        | { kind: "external" };
+}
+
+export interface RepositoryItem {
+    key: string;
+    repo_name: string;
+    /** The database collection behind the repository, if any. */
+    collection?: string;
+    /** The fields expected in the metadata, free-form if not set. */
+    schema?: MetadataSchema | null;
+    callback: RepositoryCallback;
+}
+
+/** `LabelField` in `tools_core::infra::label`. */
+export type LabelField =
+    | "id"
+    | "sku"
+    | "metadata"
+    | { metadata_key: string };
+
+/** `LabelLayout` in `tools_core::infra::label`. */
+export interface LabelLayout {
+    page_width: number;
+    page_height: number;
+    columns: number;
+    rows: number;
+    label_width: number;
+    label_height: number;
+    margin_top: number;
+    margin_left: number;
+    gap_x: number;
+    gap_y: number;
+    padding: number;
+    code: LabelField | null;
+    error_correction: "L" | "M" | "Q" | "H";
+    fields: LabelField[];
+    font_size: number;
+    border: boolean;
+}
+
+export interface LabelOptions {
+    layout: LabelLayout;
+    skip: number;
+    font_path: string | null;
 }
 
 export const columnHeaders: DataTableColumn<RowData>[] = [
@@ -44,4 +191,22 @@ export const columnHeaders: DataTableColumn<RowData>[] = [
             return row.sku.includes(String(v));
         },
     },
+    {
+        key: "created_at",
+        title: "Added",
+        width: "12rem",
+        sorter: (a: RowData, b: RowData) =>
+            Date.parse(a.created_at ?? "") - Date.parse(b.created_at ?? ""),
+        render: (row: RowData) =>
+            row.created_at ? new Date(row.created_at).toLocaleString() : "",
+    },
+    {
+        key: "updated_at",
+        title: "Updated",
+        width: "12rem",
+        sorter: (a: RowData, b: RowData) =>
+            Date.parse(a.updated_at ?? "") - Date.parse(b.updated_at ?? ""),
+        render: (row: RowData) =>
+            row.updated_at ? new Date(row.updated_at).toLocaleString() : "",
+    },
 ];
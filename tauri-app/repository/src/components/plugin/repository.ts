@@ -0,0 +1,110 @@
+import { invoke } from "@tauri-apps/api/core";
+import { Collection } from "./config";
+import {
+    AuditEntry,
+    FieldError,
+    ItemSource,
+    ItemTimestamp,
+    LabelOptions,
+    MetadataSchema,
+    RepositoryCallback,
+    RepositoryItem,
+    RowData,
+} from "./interface";
+
+/** Show metadata as `label: value` pairs in the order of the schema. */
+function formatMetadata(schema: MetadataSchema | null, metadata: string) {
+    let object: Record<string, unknown>;
+    try {
+        object = JSON.parse(metadata);
+    } catch {
+        return metadata;
+    }
+    if (!schema || typeof object !== "object" || object === null) {
+        return metadata;
+    }
+    const known = new Set(schema.fields.map((field) => field.key));
+    const pairs = schema.fields
+        .filter((field) => object[field.key] != null)
+        .map((field) => {
+            const unit = field.type === "number" && field.unit ? ` ${field.unit}` : "";
+            return `${field.label || field.key}: ${object[field.key]}${unit}`;
+        });
+    const others = Object.entries(object)
+        .filter(([key]) => !known.has(key))
+        .map(([key, value]) => `${key}: ${JSON.stringify(value)}`);
+    return [...pairs, ...others].join(", ");
+}
+
+/**
+ * Build the callbacks of a collection from the generic `repo_*` commands, so a new
+ * collection only needs to be added to the config.
+ */
+export function collectionRepo({ id: collection, name, schema }: Collection) {
+    function call<T>(command: string, args: Record<string, unknown> = {}) {
+        return invoke<T>(command, { collection, ...args });
+    }
+
+    const callback: RepositoryCallback = {
+        load: async () => {
+            try {
+                return await call<RowData[]>("repo_list");
+            } catch (e) {
+                console.error("Error loading database data:", e);
+                return [];
+            }
+        },
+        list_between: (timestamp: ItemTimestamp, from: Date, to: Date) =>
+            call<RowData[]>("repo_list_between", {
+                timestamp,
+                from: from.toISOString(),
+                to: to.toISOString(),
+            }),
+        get_by_uid: async (uid: string) =>
+            (await call<RowData | null>("repo_get", { uid })) ?? undefined,
+        get_by_sku: async (sku: string) =>
+            (await call<RowData | null>("repo_get_by_sku", { sku })) ??
+            undefined,
+        add: async (sku: string, metadata: string, source?: ItemSource) => {
+            await call("repo_add", { sku, metadata, source });
+        },
+        update: async (uid: string, sku: string, metadata: string) => {
+            await call("repo_update", { uid, sku, metadata });
+        },
+        validate: (metadata: string) =>
+            call<FieldError[]>("repo_validate", { metadata }),
+        rm: async (uid: string) => {
+            await call("repo_remove", { uid });
+        },
+        trash: () => call<RowData[]>("repo_trash"),
+        restore: async (uid: string) => {
+            await call("repo_restore", { uid });
+        },
+        purge: async (uid: string) => {
+            await call("repo_purge", { uid });
+        },
+        empty_trash: () => call<number>("repo_empty_trash"),
+        history: (uid: string) => call<AuditEntry[]>("repo_history", { uid }),
+        revert: async (uid: string, entryId: number) => {
+            await call("repo_revert", { uid, entryId });
+        },
+        format_metadata: (metadata: string) =>
+            formatMetadata(schema, metadata),
+        labels: (uids: string[], options: LabelOptions) =>
+            call<ArrayBuffer>("repo_labels", {
+                uids,
+                layout: options.layout,
+                skip: options.skip,
+                fontPath: options.font_path,
+            }),
+    };
+
+    const item: RepositoryItem = {
+        key: `collection:${collection}`,
+        repo_name: name,
+        collection,
+        schema,
+        callback,
+    };
+    return item;
+}
@@ -46,6 +46,7 @@ function create_test_data(num: number) {
 }
 
 export const sampleRepo = {
+    key: "sample",
     repo_name: "Sample Repository",
     callback: sampleRepoCallback,
 };